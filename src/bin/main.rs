@@ -74,19 +74,27 @@ async fn main(spawner: Spawner) -> ! {
     // We "steal" them using ptr::read to bypass the local lifetime
     // from esp_hal::init and satisfy the 'static demand of the task.
     let i2c0 = unsafe {
-        esp_hal::i2c::master::I2c::new(
+        lib::sensors::bus::RecoverableI2c::new(
             core::ptr::read(&peripherals.I2C0),
+            core::ptr::read(&peripherals.GPIO7),
+            core::ptr::read(&peripherals.GPIO6),
             esp_hal::i2c::master::Config::default(),
         )
         .unwrap()
-        .with_sda(core::ptr::read(&peripherals.GPIO7))
-        .with_scl(core::ptr::read(&peripherals.GPIO6))
-        .into_async()
     };
+    // The SGP41, optional SHT4x and BME280 and an SCD4x, SEN5x/SEN66 or
+    // PMSA003I if fitted share the bus.
+    let i2c0 = lib::sensors::bus::SharedI2c::new(i2c0);
 
+    let io_timeout = lib::config::CONFIG.sensor.io_timeout;
     let sgp = lib::sensors::sgp41::Sgp41::new(
         i2c0,
         (lib::config::CONFIG.sensor.polling_interval.as_millis() as f32) / 1000.0,
+    )
+    .with_timeout(io_timeout)
+    .with_tuning(
+        lib::config::CONFIG.sensor.voc_tuning,
+        lib::config::CONFIG.sensor.nox_tuning,
     );
     let uart0_config = esp_hal::uart::Config::default().with_baudrate(9600);
     let uart0 = unsafe {
@@ -96,7 +104,22 @@ async fn main(spawner: Spawner) -> ! {
             .with_tx(core::ptr::read(&peripherals.GPIO21))
             .into_async()
     };
-    let pms = lib::sensors::pms5003t::Pms5003t::new(uart0);
+    let uart0 = lib::sensors::bus::RecoverableUart::new(uart0, uart0_config);
+    let pms_link = match lib::config::CONFIG.sensor.pms_transport {
+        lib::config::PmsTransport::Uart => lib::sensors::pms5003t::PmsLink::Uart(uart0),
+        lib::config::PmsTransport::I2c => {
+            lib::sensors::pms5003t::PmsLink::I2c(lib::sensors::pms5003t::PmsI2c::new(i2c0))
+        }
+    };
+    let mut pms = lib::sensors::pms5003t::Pms5003t::new(pms_link)
+        .with_model(lib::config::CONFIG.sensor.pms_model)
+        .with_timeout(io_timeout);
+    if matches!(
+        lib::config::CONFIG.sensor.pms_transport,
+        lib::config::PmsTransport::I2c
+    ) {
+        pms = pms.with_max_read_bytes(lib::sensors::pms5003t::PMSA003I_MAX_READ_BYTES);
+    }
 
     let uart1_config = esp_hal::uart::Config::default().with_baudrate(9600);
     let uart1 = unsafe {
@@ -106,11 +129,50 @@ async fn main(spawner: Spawner) -> ! {
             .with_tx(core::ptr::read(&peripherals.GPIO1))
             .into_async()
     };
-    let s8 = lib::sensors::s8::S8::new(uart1);
-
-    let sensor_manager = lib::sensors::SensorManager::new(sgp, pms, s8);
+    let uart1 = lib::sensors::bus::RecoverableUart::new(uart1, uart1_config);
+
+    let sht = lib::sensors::sht4x::Sht4x::new(i2c0)
+        .with_timeout(io_timeout)
+        .with_precision(lib::config::CONFIG.sensor.sht4x_precision);
+    let bme = lib::sensors::bme280::Bme280::new(i2c0)
+        .with_address(lib::config::CONFIG.sensor.bme280_address)
+        .with_timeout(io_timeout);
+    let mut sensor_manager = lib::sensors::SensorManager::new(sgp, pms)
+        .with_sht(sht)
+        .with_bme280(bme);
+    if let Some(model) = lib::config::CONFIG.sensor.sen5x {
+        sensor_manager = sensor_manager
+            .with_sen5x(lib::sensors::sen5x::Sen5x::new(i2c0, model).with_timeout(io_timeout));
+    } else if lib::config::CONFIG.sensor.sgp41_state.is_some()
+        && let Some(store) = lib::sensors::gas_index_store::GasIndexStore::new(
+            esp_storage::FlashStorage::new(peripherals.FLASH),
+            esp_hal::rtc_cntl::Rtc::new(peripherals.LPWR),
+        )
+    {
+        sensor_manager = sensor_manager.with_gas_index_store(store);
+    }
+    // A SEN5x/SEN66 also replaces the O-1PPT's second PMS5003T.
+    if lib::config::CONFIG.model.has_second_pms() && !lib::config::CONFIG.sensor.has_sen5x() {
+        sensor_manager = sensor_manager.with_second_pms(
+            lib::sensors::pms5003t::Pms5003t::new(uart1)
+                .with_model(lib::config::CONFIG.sensor.pms_model)
+                .with_timeout(io_timeout),
+        );
+    } else if lib::config::CONFIG.sensor.has_s8() {
+        sensor_manager =
+            sensor_manager.with_s8(lib::sensors::s8::S8::new(uart1).with_timeout(io_timeout));
+    }
+    if lib::config::CONFIG.sensor.has_scd4x() {
+        sensor_manager = sensor_manager
+            .with_scd4x(lib::sensors::scd4x::Scd4x::new(i2c0).with_timeout(io_timeout));
+    }
     let sensor_data = lib::sensors::SharedSensorData::new();
-    spawner.must_spawn(lib::sensors::sensor_task(sensor_manager, sensor_data));
+    let sensor_commands = lib::sensors::SensorCommands::new();
+    spawner.must_spawn(lib::sensors::sensor_task(
+        sensor_manager,
+        sensor_data,
+        sensor_commands,
+    ));
 
     let last_scrape_secs = picoserve::make_static!(AtomicU32, AtomicU32::new(0));
 
@@ -122,7 +184,7 @@ async fn main(spawner: Spawner) -> ! {
         last_scrape_secs,
     ));
 
-    let web_app = lib::web::WebApp::new(sensor_data, last_scrape_secs);
+    let web_app = lib::web::WebApp::new(sensor_data, sensor_commands, last_scrape_secs);
     for id in 0..lib::web::WEB_TASK_POOL_SIZE {
         spawner.must_spawn(lib::web::web_task(
             id,
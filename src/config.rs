@@ -5,6 +5,54 @@
 
 use embassy_time::Duration;
 
+use crate::sensors::co2_compensation;
+use crate::sensors::gas_index::{AlgorithmType, TuningParameters};
+use crate::sensors::pm_correction::Pm25Correction;
+use crate::sensors::pms_aggregate::PmsAggregation;
+use crate::sensors::pms5003t::PmsModel;
+use crate::sensors::scd4x::Scd4xMode;
+use crate::sensors::sen5x::Sen5xModel;
+use crate::sensors::sht4x::Sht4xPrecision;
+
+/// Hardware variant the firmware is built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// Open Air with a PMS5003T, SenseAir S8 and SGP41.
+    O1Pst,
+    /// Open Air with two PMS5003T sensors and an SGP41; no CO2 sensor.
+    O1Ppt,
+}
+
+impl Model {
+    pub const fn has_s8(self) -> bool {
+        matches!(self, Model::O1Pst)
+    }
+
+    pub const fn has_second_pms(self) -> bool {
+        matches!(self, Model::O1Ppt)
+    }
+}
+
+/// CO2 sensor fitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Co2Sensor {
+    /// SenseAir S8 on UART1.
+    S8,
+    /// Sensirion SCD40/SCD41 on the SGP41's I2C bus.
+    Scd4x,
+    /// The CO2 channel of a Sensirion SEN66.
+    Sen66,
+}
+
+/// How the PMS sensor is connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmsTransport {
+    /// UART0.
+    Uart,
+    /// PMSA003I on the SGP41's I2C bus.
+    I2c,
+}
+
 /// WiFi configuration settings.
 #[derive(Debug, Clone, Copy)]
 pub struct WifiConfig {
@@ -18,6 +66,13 @@ pub struct WifiConfig {
     pub power_save_mode: esp_radio::wifi::PowerSaveMode,
 }
 
+/// Web server configuration settings.
+#[derive(Debug, Clone, Copy)]
+pub struct WebConfig {
+    /// Bearer token required by the write endpoints. `None` disables them.
+    pub api_token: Option<&'static str>,
+}
+
 /// Watchdog configuration settings.
 #[derive(Debug, Clone, Copy)]
 pub struct WatchdogConfig {
@@ -33,18 +88,148 @@ pub struct WatchdogConfig {
     pub kick_duration_ms: u32,
 }
 
+/// PMS5003T fan/laser duty cycle.
+///
+/// The sensor is woken up, given `warmup` for the airflow to stabilize, read for
+/// `sampling_window`, then put to sleep for `sleep`.
+#[derive(Debug, Clone, Copy)]
+pub struct PmsDutyCycle {
+    /// Time to wait after wakeup before readings are used.
+    pub warmup: Duration,
+    /// How long to sample once warmed up.
+    pub sampling_window: Duration,
+    /// How long to sleep between sampling windows.
+    pub sleep: Duration,
+}
+
+/// SGP41 heater duty cycle.
+///
+/// The heater is turned off for `off`, then the sensor is self-tested and
+/// conditioned again and measures for `on`. There is no wall clock, so the
+/// cycle runs from boot.
+#[derive(Debug, Clone, Copy)]
+pub struct Sgp41HeaterSchedule {
+    /// How long to measure between quiet periods.
+    pub on: Duration,
+    /// How long each quiet period lasts.
+    pub off: Duration,
+}
+
+/// Fixed barometric pressure of the site, used for CO2 compensation when no
+/// barometer is fitted.
+#[derive(Debug, Clone, Copy)]
+pub enum SitePressure {
+    /// Derived from the altitude with the standard barometric formula.
+    Altitude {
+        meters: f32,
+    },
+    Pressure {
+        pascals: f32,
+    },
+}
+
+impl SitePressure {
+    pub fn pascals(&self) -> f32 {
+        match *self {
+            SitePressure::Altitude { meters } => co2_compensation::pressure_at_altitude(meters),
+            SitePressure::Pressure { pascals } => pascals,
+        }
+    }
+}
+
+/// SCD4x settings applied at boot.
+#[derive(Debug, Clone, Copy)]
+pub struct Scd4xConfig {
+    pub mode: Scd4xMode,
+    /// Altitude for pressure compensation. `None` leaves the sensor's setting.
+    pub altitude_meters: Option<u16>,
+    /// Automatic self-calibration. `None` leaves the sensor's setting.
+    pub asc: Option<bool>,
+}
+
+/// Saving the SGP41 VOC gas index baseline to flash.
+#[derive(Debug, Clone, Copy)]
+pub struct GasIndexPersistence {
+    /// How often the learned state is saved.
+    pub save_interval: Duration,
+    /// Saved states older than this are discarded at boot.
+    pub max_age: Duration,
+    /// Whether to restore a state whose age is unknown because the chip lost
+    /// power (including resets by the external watchdog).
+    pub restore_unknown_age: bool,
+}
+
 /// Sensor configuration settings.
 #[derive(Debug, Clone, Copy)]
 pub struct SensorConfig {
     /// The interval at which sensors are polled.
     pub polling_interval: Duration,
+    /// PMS sensor fitted, which decides what its frames carry.
+    pub pms_model: PmsModel,
+    /// How the PMS sensor (the first, on the O-1PPT) is connected.
+    pub pms_transport: PmsTransport,
+    /// Whether to put the PMS5003T in passive mode and request each frame.
+    pub pms_passive_mode: bool,
+    /// Sleep the PMS5003T between sampling windows. `None` runs it continuously.
+    pub pms_duty_cycle: Option<PmsDutyCycle>,
+    /// How PMS5003T frames received during a poll are combined. `None` uses
+    /// the first valid frame.
+    pub pms_aggregation: Option<PmsAggregation>,
+    /// Sensirion SEN5x/SEN66 used in place of the PMS5003T, SGP41 and S8.
+    /// `None` uses those.
+    pub sen5x: Option<Sen5xModel>,
+    /// CO2 sensor fitted, if any.
+    pub co2_sensor: Option<Co2Sensor>,
+    pub scd4x: Scd4xConfig,
+    /// Pressure CO2 is compensated for when no BME280/BMP280 is fitted.
+    /// `None` leaves readings uncompensated without one.
+    pub site_pressure: Option<SitePressure>,
+    /// SenseAir S8 ABC period in hours to apply at boot; 0 disables ABC.
+    /// `None` leaves the sensor's setting alone.
+    pub s8_abc_period_hours: Option<u16>,
+    /// Correction applied to PM2.5 for the corrected gauge. `None` disables it.
+    pub pm25_correction: Option<Pm25Correction>,
+    /// How long a single sensor read or write may take before it fails.
+    pub io_timeout: Duration,
+    /// Consecutive failed polls of a sensor before its bus is reset.
+    pub recovery_threshold: u32,
+    /// Persist the SGP41 VOC baseline across reboots. `None` disables it.
+    pub sgp41_state: Option<GasIndexPersistence>,
+    /// Turn the SGP41 heater off periodically. `None` keeps it on.
+    pub sgp41_heater_schedule: Option<Sgp41HeaterSchedule>,
+    /// VOC gas index tuning applied at boot; can be changed at runtime.
+    pub voc_tuning: TuningParameters,
+    /// NOx gas index tuning applied at boot; can be changed at runtime.
+    pub nox_tuning: TuningParameters,
+    /// Measurement precision of the SHT4x, if one is fitted.
+    pub sht4x_precision: Sht4xPrecision,
+    /// I2C address probed for a BME280/BMP280.
+    pub bme280_address: u8,
+}
+
+impl SensorConfig {
+    pub const fn has_s8(&self) -> bool {
+        matches!(self.co2_sensor, Some(Co2Sensor::S8))
+    }
+
+    pub const fn has_scd4x(&self) -> bool {
+        matches!(self.co2_sensor, Some(Co2Sensor::Scd4x))
+    }
+
+    pub const fn has_sen5x(&self) -> bool {
+        self.sen5x.is_some()
+    }
 }
 
 /// Global application configuration.
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
+    /// Hardware variant.
+    pub model: Model,
     /// WiFi configuration.
     pub wifi: WifiConfig,
+    /// Web server configuration.
+    pub web: WebConfig,
     /// Watchdog configuration.
     pub watchdog: WatchdogConfig,
     /// Sensor configuration.
@@ -56,7 +241,29 @@ pub struct Config {
 impl Config {
     /// Creates a new configuration from compile-time environment variables.
     const fn new() -> Self {
+        let model = match option_env!("AIRGRADIENT_MODEL") {
+            Some("O-1PST") | None => Model::O1Pst,
+            Some("O-1PPT") => Model::O1Ppt,
+            Some(_) => panic!("Invalid AIRGRADIENT_MODEL value"),
+        };
+        let sen5x = match option_env!("SEN5X_MODEL") {
+            Some("sen5x") => Some(Sen5xModel::Sen5x),
+            Some("sen66") => Some(Sen5xModel::Sen66),
+            None => None,
+            Some(_) => panic!("Invalid SEN5X_MODEL value"),
+        };
+        let (pms_model, pms_transport) = match option_env!("PMS_MODEL") {
+            Some("pms5003t") | None => (PmsModel::Pms5003t, PmsTransport::Uart),
+            Some("pms7003") | Some("pmsa003") => (PmsModel::Pms7003, PmsTransport::Uart),
+            Some("pmsa003i") if model.has_second_pms() => {
+                panic!("The O-1PPT's PMS sensors are on UART")
+            }
+            Some("pmsa003i") => (PmsModel::Pms7003, PmsTransport::I2c),
+            Some(_) => panic!("Invalid PMS_MODEL value"),
+        };
+        let pms_i2c = matches!(pms_transport, PmsTransport::I2c);
         Self {
+            model,
             wifi: WifiConfig {
                 ssid: option_env!("WIFI_SSID"),
                 password: option_env!("WIFI_PASSWORD"),
@@ -69,6 +276,9 @@ impl Config {
                     None => esp_radio::wifi::PowerSaveMode::Minimum,
                 },
             },
+            web: WebConfig {
+                api_token: option_env!("API_TOKEN"),
+            },
             watchdog: WatchdogConfig {
                 tick_interval: Duration::from_secs(60),
                 wifi_timeout: Duration::from_secs(300), // 5 minutes
@@ -78,11 +288,294 @@ impl Config {
             },
             sensor: SensorConfig {
                 polling_interval: Duration::from_secs(2),
+                pms_model,
+                pms_transport,
+                pms_passive_mode: match option_env!("PMS_PASSIVE_MODE") {
+                    Some("true") if pms_i2c => panic!("The PMSA003I has no passive mode"),
+                    Some("true") => true,
+                    _ => false,
+                },
+                pms_duty_cycle: match option_env!("PMS_SLEEP_SECS") {
+                    Some(_) if pms_i2c => panic!("The PMSA003I can't be put to sleep"),
+                    Some(sleep_secs) => Some(PmsDutyCycle {
+                        warmup: Duration::from_secs(30),
+                        sampling_window: Duration::from_secs(parse_u64(
+                            option_env!("PMS_SAMPLE_SECS"),
+                            30,
+                        )),
+                        sleep: Duration::from_secs(parse_u64(Some(sleep_secs), 0)),
+                    }),
+                    None => None,
+                },
+                pms_aggregation: match option_env!("PMS_AGGREGATION") {
+                    Some("median") | None => Some(PmsAggregation::Median),
+                    Some("trimmed_mean") => Some(PmsAggregation::TrimmedMean),
+                    Some("first") => None,
+                    Some(_) => panic!("Invalid PMS_AGGREGATION value"),
+                },
+                sen5x,
+                co2_sensor: match option_env!("CO2_SENSOR") {
+                    None | Some("sen66") if matches!(sen5x, Some(Sen5xModel::Sen66)) => {
+                        Some(Co2Sensor::Sen66)
+                    }
+                    Some("sen66") => panic!("CO2_SENSOR=sen66 needs SEN5X_MODEL=sen66"),
+                    None if sen5x.is_some() => None,
+                    None if model.has_s8() => Some(Co2Sensor::S8),
+                    Some("s8") if sen5x.is_some() => {
+                        panic!("The S8 isn't read when a SEN5x/SEN66 is fitted")
+                    }
+                    Some("s8") if model.has_second_pms() => {
+                        panic!("The O-1PPT's second PMS5003T uses the S8's UART")
+                    }
+                    Some("s8") => Some(Co2Sensor::S8),
+                    Some("scd4x") => Some(Co2Sensor::Scd4x),
+                    Some("none") | None => None,
+                    Some(_) => panic!("Invalid CO2_SENSOR value"),
+                },
+                scd4x: Scd4xConfig {
+                    mode: match option_env!("SCD4X_MODE") {
+                        Some("periodic") | None => Scd4xMode::Periodic,
+                        Some("low_power") => Scd4xMode::LowPowerPeriodic,
+                        Some("single_shot") => Scd4xMode::SingleShot,
+                        Some(_) => panic!("Invalid SCD4X_MODE value"),
+                    },
+                    altitude_meters: match option_env!("SCD4X_ALTITUDE_METERS") {
+                        // The SCD4x accepts 0-3000 m.
+                        Some(meters) => match parse_u64(Some(meters), 0) {
+                            meters if meters > 3000 => {
+                                panic!("SCD4X_ALTITUDE_METERS out of range")
+                            }
+                            meters => Some(meters as u16),
+                        },
+                        None => None,
+                    },
+                    asc: match option_env!("SCD4X_ASC") {
+                        Some("true") => Some(true),
+                        Some("false") => Some(false),
+                        None => None,
+                        Some(_) => panic!("Invalid SCD4X_ASC value"),
+                    },
+                },
+                site_pressure: match (
+                    option_env!("ALTITUDE_METERS"),
+                    option_env!("SITE_PRESSURE_PA"),
+                ) {
+                    (Some(_), Some(_)) => {
+                        panic!("Set only one of ALTITUDE_METERS and SITE_PRESSURE_PA")
+                    }
+                    // The barometric formula yields NaN above ~44 km; accept
+                    // only plausible sites.
+                    (Some(meters), None) => match parse_f32(Some(meters), 0.0) {
+                        meters if meters < -500.0 || meters > 9000.0 => {
+                            panic!("ALTITUDE_METERS out of range")
+                        }
+                        meters => Some(SitePressure::Altitude { meters }),
+                    },
+                    (None, Some(pascals)) => match parse_f32(Some(pascals), 0.0) {
+                        pascals if pascals < 30_000.0 || pascals > 110_000.0 => {
+                            panic!("SITE_PRESSURE_PA out of range")
+                        }
+                        pascals => Some(SitePressure::Pressure { pascals }),
+                    },
+                    (None, None) => None,
+                },
+                s8_abc_period_hours: match option_env!("S8_ABC_PERIOD_HOURS") {
+                    Some(hours) => match parse_u64(Some(hours), 0) {
+                        hours if hours > u16::MAX as u64 => {
+                            panic!("S8_ABC_PERIOD_HOURS out of range")
+                        }
+                        hours => Some(hours as u16),
+                    },
+                    None => None,
+                },
+                pm25_correction: match option_env!("PM25_CORRECTION") {
+                    Some("airgradient") | None => Some(Pm25Correction::AirGradient),
+                    Some("epa_2021") => Some(Pm25Correction::Epa2021),
+                    Some("linear") => Some(Pm25Correction::Linear {
+                        slope: parse_f32(option_env!("PM25_CORRECTION_SLOPE"), 1.0),
+                        intercept: parse_f32(option_env!("PM25_CORRECTION_INTERCEPT"), 0.0),
+                    }),
+                    Some("none") => None,
+                    Some(_) => panic!("Invalid PM25_CORRECTION value"),
+                },
+                io_timeout: Duration::from_millis(parse_u64(
+                    option_env!("SENSOR_IO_TIMEOUT_MS"),
+                    3000,
+                )),
+                recovery_threshold: parse_u64(option_env!("SENSOR_RECOVERY_THRESHOLD"), 3) as u32,
+                sgp41_state: match parse_u64(option_env!("SGP41_STATE_SAVE_SECS"), 600) {
+                    0 => None,
+                    save_secs => Some(GasIndexPersistence {
+                        save_interval: Duration::from_secs(save_secs),
+                        max_age: Duration::from_secs(parse_u64(
+                            option_env!("SGP41_STATE_MAX_AGE_SECS"),
+                            600,
+                        )),
+                        restore_unknown_age: matches!(
+                            option_env!("SGP41_STATE_RESTORE_AFTER_POWER_LOSS"),
+                            Some("true")
+                        ),
+                    }),
+                },
+                sgp41_heater_schedule: match option_env!("SGP41_HEATER_OFF_SECS") {
+                    Some(off_secs) => Some(Sgp41HeaterSchedule {
+                        on: Duration::from_secs(parse_u64(
+                            option_env!("SGP41_HEATER_ON_SECS"),
+                            3600,
+                        )),
+                        off: Duration::from_secs(parse_u64(Some(off_secs), 0)),
+                    }),
+                    None => None,
+                },
+                voc_tuning: checked_tuning(
+                    TuningParameters {
+                        index_offset: parse_i32(
+                            option_env!("VOC_INDEX_OFFSET"),
+                            TuningParameters::VOC_DEFAULT.index_offset,
+                        ),
+                        learning_time_offset_hours: parse_i32(
+                            option_env!("VOC_LEARNING_TIME_OFFSET_HOURS"),
+                            TuningParameters::VOC_DEFAULT.learning_time_offset_hours,
+                        ),
+                        learning_time_gain_hours: parse_i32(
+                            option_env!("VOC_LEARNING_TIME_GAIN_HOURS"),
+                            TuningParameters::VOC_DEFAULT.learning_time_gain_hours,
+                        ),
+                        gating_max_duration_minutes: parse_i32(
+                            option_env!("VOC_GATING_MAX_DURATION_MINUTES"),
+                            TuningParameters::VOC_DEFAULT.gating_max_duration_minutes,
+                        ),
+                        std_initial: parse_i32(
+                            option_env!("VOC_STD_INITIAL"),
+                            TuningParameters::VOC_DEFAULT.std_initial,
+                        ),
+                        gain_factor: parse_i32(
+                            option_env!("VOC_GAIN_FACTOR"),
+                            TuningParameters::VOC_DEFAULT.gain_factor,
+                        ),
+                    },
+                    AlgorithmType::Voc,
+                ),
+                nox_tuning: checked_tuning(
+                    TuningParameters {
+                        index_offset: parse_i32(
+                            option_env!("NOX_INDEX_OFFSET"),
+                            TuningParameters::NOX_DEFAULT.index_offset,
+                        ),
+                        learning_time_offset_hours: parse_i32(
+                            option_env!("NOX_LEARNING_TIME_OFFSET_HOURS"),
+                            TuningParameters::NOX_DEFAULT.learning_time_offset_hours,
+                        ),
+                        // Fixed for NOx.
+                        learning_time_gain_hours: TuningParameters::NOX_DEFAULT
+                            .learning_time_gain_hours,
+                        gating_max_duration_minutes: parse_i32(
+                            option_env!("NOX_GATING_MAX_DURATION_MINUTES"),
+                            TuningParameters::NOX_DEFAULT.gating_max_duration_minutes,
+                        ),
+                        std_initial: TuningParameters::NOX_DEFAULT.std_initial,
+                        gain_factor: parse_i32(
+                            option_env!("NOX_GAIN_FACTOR"),
+                            TuningParameters::NOX_DEFAULT.gain_factor,
+                        ),
+                    },
+                    AlgorithmType::Nox,
+                ),
+                sht4x_precision: match option_env!("SHT4X_PRECISION") {
+                    Some("high") | None => Sht4xPrecision::High,
+                    Some("medium") => Sht4xPrecision::Medium,
+                    Some("low") => Sht4xPrecision::Low,
+                    Some(_) => panic!("Invalid SHT4X_PRECISION value"),
+                },
+                bme280_address: match option_env!("BME280_ADDRESS") {
+                    Some("0x76") | None => 0x76,
+                    Some("0x77") => 0x77,
+                    Some(_) => panic!("Invalid BME280_ADDRESS value"),
+                },
             },
             print_status_loop: matches!(option_env!("PRINT_STATUS_LOOP"), Some("true")),
         }
     }
 }
 
+/// Parses an optional decimal environment variable, falling back to `default`.
+const fn parse_u64(value: Option<&str>, default: u64) -> u64 {
+    match value {
+        Some(v) => match u64::from_str_radix(v, 10) {
+            Ok(n) => n,
+            Err(_) => panic!("Invalid integer in environment variable"),
+        },
+        None => default,
+    }
+}
+
+/// Parses an optional decimal environment variable into an `i32`, falling
+/// back to `default`.
+const fn parse_i32(value: Option<&str>, default: i32) -> i32 {
+    match value {
+        Some(v) => match i32::from_str_radix(v, 10) {
+            Ok(n) => n,
+            Err(_) => panic!("Invalid integer in environment variable"),
+        },
+        None => default,
+    }
+}
+
+/// Rejects gas index tuning outside the ranges Sensirion documents.
+const fn checked_tuning(
+    tuning: TuningParameters,
+    algorithm_type: AlgorithmType,
+) -> TuningParameters {
+    if !tuning.is_valid(algorithm_type) {
+        match algorithm_type {
+            AlgorithmType::Voc => panic!("VOC_* tuning parameter out of range"),
+            AlgorithmType::Nox => panic!("NOX_* tuning parameter out of range"),
+        }
+    }
+    tuning
+}
+
+/// Parses an optional decimal environment variable such as "-1.25", falling
+/// back to `default`.
+const fn parse_f32(value: Option<&str>, default: f32) -> f32 {
+    let Some(v) = value else {
+        return default;
+    };
+    let bytes = v.as_bytes();
+    let mut i = 0;
+    let negative = !bytes.is_empty() && bytes[0] == b'-';
+    if negative || (!bytes.is_empty() && bytes[0] == b'+') {
+        i += 1;
+    }
+
+    let mut result = 0.0f32;
+    let mut scale = 1.0f32;
+    let mut seen_dot = false;
+    let mut digits = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'.' && !seen_dot {
+            seen_dot = true;
+        } else if b.is_ascii_digit() {
+            let digit = (b - b'0') as f32;
+            if seen_dot {
+                scale /= 10.0;
+                result += digit * scale;
+            } else {
+                result = result * 10.0 + digit;
+            }
+            digits += 1;
+        } else {
+            panic!("Invalid number in environment variable");
+        }
+        i += 1;
+    }
+    if digits == 0 {
+        panic!("Invalid number in environment variable");
+    }
+
+    if negative { -result } else { result }
+}
+
 /// Global configuration instance.
 pub static CONFIG: Config = Config::new();
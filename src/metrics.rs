@@ -1,3 +1,7 @@
+use crate::config::CONFIG;
+use crate::sensors::gas_index::GasIndexInternals;
+use crate::sensors::pms5003t::{PmsData, PmsStats};
+use crate::sensors::sgp41::{self, Sgp41State};
 use crate::{device::DeviceInfo, sensors::SharedSensorData};
 use core::fmt::{self, Write as FmtWrite};
 use core::sync::atomic::{AtomicU32, Ordering};
@@ -26,7 +30,7 @@ impl SystemMetrics {
 pub struct MetricsContent(pub String);
 enum MetricsResponse {
     Metrics(MetricsContent),
-    Error(&'static str),
+    Error(heapless::String<64>),
 }
 
 impl Content for MetricsResponse {
@@ -64,20 +68,27 @@ impl<'a, W: FmtWrite> MetricFormatter<'a, W> {
         Self { writer }
     }
 
-    fn write_gauge(
+    fn write_header(
         &mut self,
         name: &str,
         help: &str,
+        kind: &str,
         unit: Option<&str>,
-        value: impl fmt::Display,
-        labels: Option<&str>,
     ) -> fmt::Result {
         writeln!(self.writer, "# HELP {} {}", name, help)?;
-        writeln!(self.writer, "# TYPE {} gauge", name)?;
+        writeln!(self.writer, "# TYPE {} {}", name, kind)?;
         if let Some(u) = unit {
             writeln!(self.writer, "# UNIT {} {}", name, u)?;
         }
+        Ok(())
+    }
 
+    fn write_sample(
+        &mut self,
+        name: &str,
+        value: impl fmt::Display,
+        labels: Option<&str>,
+    ) -> fmt::Result {
         write!(self.writer, "{}", name)?;
         if let Some(lbl) = labels {
             write!(self.writer, "{{{}}}", lbl)?;
@@ -87,8 +98,144 @@ impl<'a, W: FmtWrite> MetricFormatter<'a, W> {
         writeln!(self.writer, " {}", value)?;
         Ok(())
     }
+
+    fn write_gauge(
+        &mut self,
+        name: &str,
+        help: &str,
+        unit: Option<&str>,
+        value: impl fmt::Display,
+        labels: Option<&str>,
+    ) -> fmt::Result {
+        self.write_header(name, help, "gauge", unit)?;
+        self.write_sample(name, value, labels)
+    }
+
+    /// Write a counter sample; OpenMetrics counters carry a `_total` suffix.
+    fn write_counter_sample(
+        &mut self,
+        name: &str,
+        value: impl fmt::Display,
+        labels: Option<&str>,
+    ) -> fmt::Result {
+        write!(self.writer, "{}_total", name)?;
+        if let Some(lbl) = labels {
+            write!(self.writer, "{{{}}}", lbl)?;
+        } else {
+            write!(self.writer, "{{}}")?;
+        }
+        writeln!(self.writer, " {}", value)?;
+        Ok(())
+    }
 }
 
+/// Metric name, help, unit and accessor for a per-sensor value.
+type FieldMetric<T, V> = (
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    fn(&T) -> V,
+);
+
+/// Gas index algorithm gauges, reported per algorithm.
+const GAS_INDEX_GAUGES: [FieldMetric<GasIndexInternals, f32>; 4] = [
+    (
+        "airgradient_sgp41_gas_index_mean",
+        "Gas index algorithm estimate of the raw signal mean in ticks",
+        None,
+        |i| i.mean,
+    ),
+    (
+        "airgradient_sgp41_gas_index_std",
+        "Gas index algorithm estimate of the raw signal standard deviation in ticks",
+        None,
+        |i| i.std,
+    ),
+    (
+        "airgradient_sgp41_gas_index_gating_duration_minutes",
+        "Accumulated time gas index learning has been gated by a high index",
+        Some("minutes"),
+        |i| i.gating_duration_minutes,
+    ),
+    (
+        "airgradient_sgp41_gas_index_learning_seconds",
+        "Learning time seen by the gas index estimator, saturating at about 9 h",
+        Some("seconds"),
+        |i| i.learning_secs,
+    ),
+];
+
+/// Per-channel PMS7003 counts, reported when a second sensor is fitted.
+const PMS_CHANNEL_OPTIONAL_GAUGES: [FieldMetric<PmsData, Option<u16>>; 2] = [
+    (
+        "airgradient_pms_pm5_p100ml",
+        "PM5.0 count",
+        Some("p100ml"),
+        |d| d.pm50_count,
+    ),
+    (
+        "airgradient_pms_pm10_p100ml",
+        "PM10 count",
+        Some("p100ml"),
+        |d| d.pm100_count,
+    ),
+];
+
+/// Per-channel PMS gauges, reported when a second sensor is fitted.
+const PMS_CHANNEL_GAUGES: [FieldMetric<PmsData, u16>; 10] = [
+    (
+        "airgradient_pms_pm0d3_p100ml",
+        "PM0.3",
+        Some("p100ml"),
+        |d| d.pm03_count,
+    ),
+    (
+        "airgradient_pms_pm0d5_p100ml",
+        "PM0.5",
+        Some("p100ml"),
+        |d| d.pm05_count,
+    ),
+    (
+        "airgradient_pms_pm1_p100ml",
+        "PM1.0 count",
+        Some("p100ml"),
+        |d| d.pm10_count,
+    ),
+    (
+        "airgradient_pms_pm2d5_p100ml",
+        "PM2.5 count",
+        Some("p100ml"),
+        |d| d.pm25_count,
+    ),
+    (
+        "airgradient_pms_pm1_cf1_ugm3",
+        "PM1.0 CF=1",
+        Some("ugm3"),
+        |d| d.pm1_cf1,
+    ),
+    (
+        "airgradient_pms_pm2d5_cf1_ugm3",
+        "PM2.5 CF=1",
+        Some("ugm3"),
+        |d| d.pm25_cf1,
+    ),
+    (
+        "airgradient_pms_pm10_cf1_ugm3",
+        "PM10 CF=1",
+        Some("ugm3"),
+        |d| d.pm10_cf1,
+    ),
+    ("airgradient_pms_pm1_ugm3", "PM1.0", Some("ugm3"), |d| {
+        d.pm1_ae
+    }),
+    ("airgradient_pms_pm2d5_ugm3", "PM2.5", Some("ugm3"), |d| {
+        d.pm25_ae
+    }),
+    ("airgradient_pms_pm10_ugm3", "PM10", Some("ugm3"), |d| {
+        d.pm10_ae
+    }),
+];
+
 pub async fn metrics_handler(
     shared_sensor_data: SharedSensorData,
     device_info: DeviceInfo,
@@ -104,9 +251,19 @@ pub async fn metrics_handler(
     };
 
     if !sensor_data.initialized {
+        let mut status = heapless::String::new();
+        let _ = match sensor_data.sgp_state {
+            Sgp41State::Conditioning { steps } => writeln!(
+                status,
+                "Sensors are initializing: SGP41 conditioning {}/{} s",
+                steps,
+                sgp41::CONDITIONING_STEPS
+            ),
+            _ => writeln!(status, "Sensors are initializing"),
+        };
         return (
             StatusCode::SERVICE_UNAVAILABLE,
-            MetricsResponse::Error("Sensors are initializing\n"),
+            MetricsResponse::Error(status),
         );
     }
 
@@ -127,7 +284,7 @@ pub async fn metrics_handler(
         let mut lb = String::new();
         let _ = write!(
             lb,
-            "version=\"{}\",commit=\"{}\",build_type=\"{}\",airgradient_serial_number=\"{}\",mac_address=\"{}\",reset_reason=\"{}\"",
+            "version=\"{}\",commit=\"{}\",build_type=\"{}\",airgradient_serial_number=\"{}\",mac_address=\"{}\",reset_reason=\"{}\",pms_firmware_version=\"",
             version,
             commit,
             build_type,
@@ -135,6 +292,10 @@ pub async fn metrics_handler(
             &device_info.mac_address,
             reset_reason
         );
+        if let Some(pms) = sensor_data.pms_a.data {
+            let _ = write!(lb, "{}", pms.firmware_version);
+        }
+        let _ = write!(lb, "\"");
         lb
     };
 
@@ -146,6 +307,100 @@ pub async fn metrics_handler(
         Some(&labels),
     );
 
+    // Sensor identification, one series per sensor. Values are empty until
+    // the sensor has been read.
+    let _ = mf.write_header(
+        "airgradient_sensor_info",
+        "Sensor identification",
+        "gauge",
+        Some("info"),
+    );
+    let mut report_info = |lbl: &str| {
+        let _ = mf.write_sample("airgradient_sensor_info", 1, Some(lbl));
+    };
+    let mut lbl: heapless::String<128> = heapless::String::new();
+    if CONFIG.sensor.has_sen5x() {
+        lbl.clear();
+        match &sensor_data.sen_info {
+            Some(info) => {
+                let _ = write!(
+                    lbl,
+                    "sensor=\"sen\",product_name=\"{}\",serial_number=\"{}\"",
+                    info.product_name, info.serial_number
+                );
+            }
+            None => {
+                let _ = write!(lbl, "sensor=\"sen\",product_name=\"\",serial_number=\"\"");
+            }
+        }
+        report_info(&lbl);
+    } else {
+        let pms_channels = [
+            ("pms", Some(sensor_data.pms_a)),
+            ("pms_b", sensor_data.pms_b),
+        ];
+        for (name, channel) in pms_channels {
+            let Some(channel) = channel else { continue };
+            lbl.clear();
+            let _ = write!(lbl, "sensor=\"{}\",firmware_version=\"", name);
+            if let Some(pms) = channel.data {
+                let _ = write!(lbl, "{}", pms.firmware_version);
+            }
+            let _ = write!(lbl, "\"");
+            report_info(&lbl);
+        }
+
+        lbl.clear();
+        let _ = write!(lbl, "sensor=\"sgp\",serial_number=\"");
+        if let Some(serial) = sensor_data.sgp_serial_number {
+            let _ = write!(lbl, "{:012x}", serial);
+        }
+        let _ = write!(lbl, "\"");
+        report_info(&lbl);
+    }
+
+    if let Some(serial) = sensor_data.sht_serial_number {
+        lbl.clear();
+        let _ = write!(lbl, "sensor=\"sht\",serial_number=\"{:08x}\"", serial);
+        report_info(&lbl);
+    }
+
+    if let Some(chip) = sensor_data.bme_chip {
+        lbl.clear();
+        let _ = write!(lbl, "sensor=\"bme\",chip=\"{}\"", chip.name());
+        report_info(&lbl);
+    }
+
+    if CONFIG.sensor.has_scd4x() {
+        lbl.clear();
+        let _ = write!(lbl, "sensor=\"scd\",serial_number=\"");
+        if let Some(serial) = sensor_data.scd_serial_number {
+            let _ = write!(lbl, "{:012x}", serial);
+        }
+        let _ = write!(lbl, "\"");
+        report_info(&lbl);
+    }
+
+    if CONFIG.sensor.has_s8() {
+        lbl.clear();
+        match sensor_data.s8_info {
+            Some(info) => {
+                let _ = write!(
+                    lbl,
+                    "sensor=\"s8\",type_id=\"{:06x}\",firmware_version=\"{}.{}\",serial_number=\"{:08x}\"",
+                    info.type_id, info.firmware_version.0, info.firmware_version.1, info.sensor_id
+                );
+            }
+            None => {
+                let _ = write!(
+                    lbl,
+                    "sensor=\"s8\",type_id=\"\",firmware_version=\"\",serial_number=\"\""
+                );
+            }
+        }
+        report_info(&lbl);
+    }
+
     // System Metrics
     let sys = SystemMetrics::capture();
     let _ = mf.write_gauge(
@@ -172,65 +427,449 @@ pub async fn metrics_handler(
 
     // Sensor Data
     let s = &sensor_data;
-    let _ = mf.write_gauge(
-        "airgradient_pm0d3_p100ml",
-        "PM0.3",
-        Some("p100ml"),
-        s.pm03_count,
-        None,
-    );
-    let _ = mf.write_gauge(
-        "airgradient_pm0d5_p100ml",
-        "PM0.5",
-        Some("p100ml"),
-        s.pm05_count,
-        None,
-    );
-    let _ = mf.write_gauge(
-        "airgradient_pm1_p100ml",
-        "PM1.0 count",
-        Some("p100ml"),
-        s.pm10_count,
-        None,
-    );
-    let _ = mf.write_gauge(
-        "airgradient_pm2d5_p100ml",
-        "PM2.5 count",
-        Some("p100ml"),
-        s.pm25_count,
-        None,
-    );
-    let _ = mf.write_gauge("airgradient_pm1_ugm3", "PM1.0", Some("ugm3"), s.pm1, None);
-    let _ = mf.write_gauge(
-        "airgradient_pm2d5_ugm3",
-        "PM2.5",
-        Some("ugm3"),
-        s.pm25,
-        None,
-    );
-    let _ = mf.write_gauge("airgradient_pm10_ugm3", "PM10", Some("ugm3"), s.pm10, None);
-    let _ = mf.write_gauge("airgradient_co2_ppm", "CO2", Some("ppm"), s.co2, None);
+    // Particle counts and CF=1 values are PMS only.
+    if let Some(pm03_count) = s.pm03_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm0d3_p100ml",
+            "PM0.3",
+            Some("p100ml"),
+            pm03_count,
+            None,
+        );
+    }
+    if let Some(pm05_count) = s.pm05_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm0d5_p100ml",
+            "PM0.5",
+            Some("p100ml"),
+            pm05_count,
+            None,
+        );
+    }
+    if let Some(pm10_count) = s.pm10_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm1_p100ml",
+            "PM1.0 count",
+            Some("p100ml"),
+            pm10_count,
+            None,
+        );
+    }
+    if let Some(pm25_count) = s.pm25_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm2d5_p100ml",
+            "PM2.5 count",
+            Some("p100ml"),
+            pm25_count,
+            None,
+        );
+    }
+    if let Some(pm50_count) = s.pm50_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm5_p100ml",
+            "PM5.0 count",
+            Some("p100ml"),
+            pm50_count,
+            None,
+        );
+    }
+    if let Some(pm100_count) = s.pm100_count {
+        let _ = mf.write_gauge(
+            "airgradient_pm10_p100ml",
+            "PM10 count",
+            Some("p100ml"),
+            pm100_count,
+            None,
+        );
+    }
+    if let Some(pm1_cf1) = s.pm1_cf1 {
+        let _ = mf.write_gauge(
+            "airgradient_pm1_cf1_ugm3",
+            "PM1.0 CF=1",
+            Some("ugm3"),
+            pm1_cf1,
+            None,
+        );
+    }
+    if let Some(pm25_cf1) = s.pm25_cf1 {
+        let _ = mf.write_gauge(
+            "airgradient_pm2d5_cf1_ugm3",
+            "PM2.5 CF=1",
+            Some("ugm3"),
+            pm25_cf1,
+            None,
+        );
+    }
+    if let Some(pm10_cf1) = s.pm10_cf1 {
+        let _ = mf.write_gauge(
+            "airgradient_pm10_cf1_ugm3",
+            "PM10 CF=1",
+            Some("ugm3"),
+            pm10_cf1,
+            None,
+        );
+    }
+    if let Some(pm1) = s.pm1 {
+        let _ = mf.write_gauge("airgradient_pm1_ugm3", "PM1.0", Some("ugm3"), pm1, None);
+    }
+    if let Some(pm25) = s.pm25 {
+        let _ = mf.write_gauge("airgradient_pm2d5_ugm3", "PM2.5", Some("ugm3"), pm25, None);
+    }
+    if let (Some(pm25_corrected), Some(correction)) =
+        (s.pm25_corrected, CONFIG.sensor.pm25_correction)
+    {
+        let mut lbl: heapless::String<32> = heapless::String::new();
+        let _ = write!(lbl, "algorithm=\"{}\"", correction.name());
+        let _ = mf.write_gauge(
+            "airgradient_pm2d5_corrected_ugm3",
+            "PM2.5 corrected",
+            Some("ugm3"),
+            pm25_corrected,
+            Some(&lbl),
+        );
+    }
+    if let Some(pm4) = s.pm4 {
+        let _ = mf.write_gauge("airgradient_pm4_ugm3", "PM4.0", Some("ugm3"), pm4, None);
+    }
+    if let Some(pm10) = s.pm10 {
+        let _ = mf.write_gauge("airgradient_pm10_ugm3", "PM10", Some("ugm3"), pm10, None);
+    }
+    if CONFIG.sensor.co2_sensor.is_some() {
+        let _ = mf.write_gauge("airgradient_co2_ppm", "CO2", Some("ppm"), s.co2, None);
+    }
+    if let Some(co2_raw) = s.co2_raw {
+        let _ = mf.write_gauge(
+            "airgradient_co2_raw_ppm",
+            "CO2 from the S8 before pressure compensation",
+            Some("ppm"),
+            co2_raw,
+            None,
+        );
+    }
+    if let Some(factor) = s.co2_compensation_factor {
+        let _ = mf.write_gauge(
+            "airgradient_co2_compensation_factor",
+            "Factor the S8 CO2 reading is multiplied by for pressure compensation",
+            None,
+            factor,
+            None,
+        );
+    }
+    if let Some(pressure) = s.pressure {
+        let _ = mf.write_gauge(
+            "airgradient_pressure_pascals",
+            "Barometric pressure",
+            Some("pascals"),
+            pressure,
+            None,
+        );
+    }
+    if let Some(temp) = s.bme_temp {
+        let _ = mf.write_gauge(
+            "airgradient_bme280_temperature_celsius",
+            "Temperature at the BME280/BMP280",
+            Some("celsius"),
+            temp,
+            None,
+        );
+    }
+    if let Some(humidity) = s.bme_humidity {
+        let _ = mf.write_gauge(
+            "airgradient_bme280_humidity_percent",
+            "Humidity at the BME280",
+            Some("percent"),
+            humidity,
+            None,
+        );
+    }
+    if let Some(calibration) = s.scd_calibration {
+        let _ = mf.write_gauge(
+            "airgradient_scd4x_calibration_success",
+            "Whether the last SCD4x forced recalibration was accepted",
+            None,
+            u8::from(calibration.correction_ppm.is_some()),
+            None,
+        );
+        let _ = mf.write_gauge(
+            "airgradient_scd4x_calibration_uptime_seconds",
+            "Uptime when the last SCD4x forced recalibration finished",
+            Some("seconds"),
+            calibration.uptime_secs,
+            None,
+        );
+        if let Some(correction) = calibration.correction_ppm {
+            let _ = mf.write_gauge(
+                "airgradient_scd4x_calibration_correction_ppm",
+                "Correction applied by the last SCD4x forced recalibration",
+                Some("ppm"),
+                correction,
+                None,
+            );
+        }
+    }
+    if CONFIG.sensor.has_s8() {
+        if let Some(calibration) = s.s8_calibration {
+            let _ = mf.write_gauge(
+                "airgradient_s8_calibration_success",
+                "Whether the last S8 background calibration was acknowledged",
+                None,
+                u8::from(calibration.succeeded),
+                None,
+            );
+            let _ = mf.write_gauge(
+                "airgradient_s8_calibration_uptime_seconds",
+                "Uptime when the last S8 background calibration finished",
+                Some("seconds"),
+                calibration.uptime_secs,
+                None,
+            );
+        }
+        if let Some(hours) = s.s8_abc_period_hours {
+            let _ = mf.write_gauge(
+                "airgradient_s8_abc_period_hours",
+                "S8 automatic baseline correction period, 0 if disabled",
+                Some("hours"),
+                hours,
+                None,
+            );
+        }
+    }
 
-    let _ = mf.write_gauge("airgradient_tvoc_index", "TVOC", Some("index"), s.voc, None);
-    let _ = mf.write_gauge("airgradient_nox_index", "NOx", Some("index"), s.nox, None);
+    if let Some(voc) = s.voc {
+        let _ = mf.write_gauge("airgradient_tvoc_index", "TVOC", Some("index"), voc, None);
+    }
+    if let Some(nox) = s.nox {
+        let _ = mf.write_gauge("airgradient_nox_index", "NOx", Some("index"), nox, None);
+    }
+    if let Some(result) = s.sgp_self_test {
+        let _ = mf.write_gauge(
+            "airgradient_sgp41_self_test_result",
+            "Result word of the last SGP41 self-test; 54272 (0xD400) is a pass",
+            None,
+            result,
+            None,
+        );
+    }
+    if let Some(heater_on) = s.sgp_heater_on {
+        let _ = mf.write_gauge(
+            "airgradient_sgp41_heater_on",
+            "Whether the SGP41 heater schedule is in its on period",
+            None,
+            heater_on as u8,
+            None,
+        );
+    }
+    if let Some(sraw_voc) = s.sraw_voc {
+        let _ = mf.write_gauge(
+            "airgradient_sgp41_sraw_voc",
+            "SGP41 raw VOC signal in ticks",
+            None,
+            sraw_voc,
+            None,
+        );
+    }
+    if let Some(sraw_nox) = s.sraw_nox {
+        let _ = mf.write_gauge(
+            "airgradient_sgp41_sraw_nox",
+            "SGP41 raw NOx signal in ticks",
+            None,
+            sraw_nox,
+            None,
+        );
+    }
+    let gas_indices = [("voc", s.voc_internals), ("nox", s.nox_internals)];
+    if gas_indices.iter().any(|(_, internals)| internals.is_some()) {
+        for (name, help, unit, value) in GAS_INDEX_GAUGES {
+            let _ = mf.write_header(name, help, "gauge", unit);
+            for (algorithm, internals) in gas_indices {
+                if let Some(internals) = internals {
+                    let mut lbl: heapless::String<16> = heapless::String::new();
+                    let _ = write!(lbl, "algorithm=\"{}\"", algorithm);
+                    let _ = mf.write_sample(name, value(&internals), Some(&lbl));
+                }
+            }
+        }
+    }
 
-    let _ = mf.write_gauge(
-        "airgradient_temperature_celsius",
-        "Temp C",
-        Some("celsius"),
-        s.temp,
-        None,
-    );
-    let _ = mf.write_gauge(
-        "airgradient_humidity_percent",
-        "Humidity",
-        Some("percent"),
-        s.humidity,
-        None,
-    );
+    if let Some(temp) = s.temp {
+        let _ = mf.write_gauge(
+            "airgradient_temperature_celsius",
+            "Temp C",
+            Some("celsius"),
+            temp,
+            None,
+        );
+    }
+    if let Some(humidity) = s.humidity {
+        let _ = mf.write_gauge(
+            "airgradient_humidity_percent",
+            "Humidity",
+            Some("percent"),
+            humidity,
+            None,
+        );
+    }
+    if !CONFIG.sensor.has_sen5x() {
+        if let Some(raw_temp) = s.raw_temp {
+            let _ = mf.write_gauge(
+                "airgradient_raw_temperature_celsius",
+                "Temp C inside the PMS5003T housing",
+                Some("celsius"),
+                raw_temp,
+                None,
+            );
+        }
+        if let Some(raw_humidity) = s.raw_humidity {
+            let _ = mf.write_gauge(
+                "airgradient_raw_humidity_percent",
+                "Humidity inside the PMS5003T housing",
+                Some("percent"),
+                raw_humidity,
+                None,
+            );
+        }
+
+        // PMS channels. Single-sensor models only have channel "a".
+        let channels = [("a", Some(s.pms_a)), ("b", s.pms_b)];
+        let channel_label = |name: &str| {
+            let mut lbl: heapless::String<16> = heapless::String::new();
+            let _ = write!(lbl, "channel=\"{}\"", name);
+            lbl
+        };
+
+        if s.pms_b.is_some() {
+            for (name, help, unit, value) in PMS_CHANNEL_GAUGES {
+                let _ = mf.write_header(name, help, "gauge", unit);
+                for (channel, pms) in channels {
+                    if let Some(data) = pms.and_then(|p| p.data) {
+                        let _ = mf.write_sample(name, value(&data), Some(&channel_label(channel)));
+                    }
+                }
+            }
+            for (name, help, unit, value) in PMS_CHANNEL_OPTIONAL_GAUGES {
+                let values = channels.map(|(channel, pms)| {
+                    (
+                        channel,
+                        pms.and_then(|p| p.data).and_then(|data| value(&data)),
+                    )
+                });
+                if values.iter().all(|(_, value)| value.is_none()) {
+                    continue;
+                }
+                let _ = mf.write_header(name, help, "gauge", unit);
+                for (channel, value) in values {
+                    if let Some(value) = value {
+                        let _ = mf.write_sample(name, value, Some(&channel_label(channel)));
+                    }
+                }
+            }
+        }
+
+        if let Some(agreement) = s.pms_agreement {
+            let _ = mf.write_gauge(
+                "airgradient_pms_channel_difference_ugm3",
+                "Absolute PM2.5 CF=1 difference between PMS channels",
+                Some("ugm3"),
+                agreement.abs_diff,
+                None,
+            );
+            let _ = mf.write_gauge(
+                "airgradient_pms_channel_difference_ratio",
+                "PM2.5 CF=1 difference between PMS channels relative to their mean",
+                Some("ratio"),
+                agreement.rel_diff,
+                None,
+            );
+            let _ = mf.write_gauge(
+                "airgradient_pms_channels_agree",
+                "Whether the PMS channels agree within 5 ugm3 or 70%",
+                None,
+                u8::from(agreement.agree),
+                None,
+            );
+        }
+
+        let _ = mf.write_header(
+            "airgradient_pms_error_code",
+            "PMS error code",
+            "gauge",
+            None,
+        );
+        for (channel, pms) in channels {
+            if let Some(data) = pms.and_then(|p| p.data) {
+                let _ = mf.write_sample(
+                    "airgradient_pms_error_code",
+                    data.error_code,
+                    Some(&channel_label(channel)),
+                );
+            }
+        }
+
+        let _ = mf.write_header(
+            "airgradient_pms_frames_aggregated",
+            "PMS frames combined into the last reading",
+            "gauge",
+            None,
+        );
+        for (channel, pms) in channels {
+            if let Some(pms) = pms {
+                let _ = mf.write_sample(
+                    "airgradient_pms_frames_aggregated",
+                    pms.frame_count,
+                    Some(&channel_label(channel)),
+                );
+            }
+        }
+
+        // PMS frame decoder counters
+        let counters: [FieldMetric<PmsStats, u32>; 4] = [
+            (
+                "airgradient_pms_frames",
+                "PMS valid frames received",
+                None,
+                |st| st.frames,
+            ),
+            (
+                "airgradient_pms_checksum_errors",
+                "PMS frames dropped for a bad checksum",
+                None,
+                |st| st.checksum_errors,
+            ),
+            (
+                "airgradient_pms_length_errors",
+                "PMS frames dropped for a bad length",
+                None,
+                |st| st.length_errors,
+            ),
+            (
+                "airgradient_pms_discarded_bytes",
+                "PMS bytes not part of a valid frame",
+                Some("bytes"),
+                |st| st.bytes_discarded,
+            ),
+        ];
+        for (name, help, unit, value) in counters {
+            let _ = mf.write_header(name, help, "counter", unit);
+            for (channel, pms) in channels {
+                if let Some(pms) = pms {
+                    let _ = mf.write_counter_sample(
+                        name,
+                        value(&pms.stats),
+                        Some(&channel_label(channel)),
+                    );
+                }
+            }
+        }
+    }
 
     // Sensor errors. Record one a gauge with a label for each sensor type.
     // If an error is present, we include error="VariantName".
+    let _ = mf.write_header(
+        "airgradient_sensor_error",
+        "Sensor Error Status",
+        "gauge",
+        None,
+    );
     let mut report_error = |name: &str, err: Option<&dyn core::fmt::Debug>| {
         let mut lbl: heapless::String<96> = heapless::String::new();
         // Base label
@@ -248,31 +887,110 @@ pub async fn metrics_handler(
             let _ = write!(lbl, ",error=\"\"");
             0
         };
-        let _ = mf.write_gauge(
-            "airgradient_sensor_error",
-            "Sensor Error Status",
-            None,
-            val,
-            Some(&lbl),
-        );
+        let _ = mf.write_sample("airgradient_sensor_error", val, Some(&lbl));
     };
 
     let errs = s.errors.as_ref();
-    report_error(
-        "pms",
-        errs.and_then(|x| x.pms.as_ref())
-            .map(|e| e as &dyn core::fmt::Debug),
-    );
-    report_error(
-        "sgp",
-        errs.and_then(|x| x.sgp.as_ref())
-            .map(|e| e as &dyn core::fmt::Debug),
-    );
-    report_error(
-        "s8",
-        errs.and_then(|x| x.s8.as_ref())
-            .map(|e| e as &dyn core::fmt::Debug),
+    if CONFIG.sensor.has_sen5x() {
+        // A transport error hides the status; otherwise report each fault flag.
+        let sen_error = errs.and_then(|x| x.sen.as_ref());
+        let sen_status = s.sen_status.filter(|status| !status.is_ok());
+        match (sen_error, sen_status) {
+            (None, Some(status)) => {
+                for fault in status.faults() {
+                    report_error("sen", Some(&fault));
+                }
+            }
+            (err, _) => report_error("sen", err.map(|e| e as &dyn core::fmt::Debug)),
+        }
+    } else {
+        report_error(
+            "pms",
+            errs.and_then(|x| x.pms.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+        report_error(
+            "sgp",
+            errs.and_then(|x| x.sgp.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+    }
+    if s.sht_serial_number.is_some() {
+        report_error(
+            "sht",
+            errs.and_then(|x| x.sht.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+    }
+    if s.pms_b.is_some() {
+        report_error(
+            "pms_b",
+            errs.and_then(|x| x.pms_b.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+    }
+    if CONFIG.sensor.has_s8() {
+        // A transport error hides the status; otherwise report each fault flag.
+        let s8_error = errs.and_then(|x| x.s8.as_ref());
+        let s8_status = s.s8_status.filter(|status| !status.is_ok());
+        match (s8_error, s8_status) {
+            (None, Some(status)) => {
+                for fault in status.faults() {
+                    report_error("s8", Some(&fault));
+                }
+            }
+            (err, _) => report_error("s8", err.map(|e| e as &dyn core::fmt::Debug)),
+        }
+    }
+    if s.bme_chip.is_some() {
+        report_error(
+            "bme",
+            errs.and_then(|x| x.bme.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+    }
+    if CONFIG.sensor.has_scd4x() {
+        report_error(
+            "scd",
+            errs.and_then(|x| x.scd.as_ref())
+                .map(|e| e as &dyn core::fmt::Debug),
+        );
+    }
+
+    let recoveries = s.recoveries;
+    let _ = mf.write_header(
+        "airgradient_sensor_recoveries",
+        "Bus resets after repeated sensor failures",
+        "counter",
+        None,
     );
+    let mut sensors = heapless::Vec::<(&str, u32), 7>::new();
+    if CONFIG.sensor.has_sen5x() {
+        let _ = sensors.push(("sen", recoveries.sen));
+    } else {
+        let _ = sensors.push(("pms", recoveries.pms));
+        let _ = sensors.push(("sgp", recoveries.sgp));
+    }
+    if s.sht_serial_number.is_some() {
+        let _ = sensors.push(("sht", recoveries.sht));
+    }
+    if s.pms_b.is_some() {
+        let _ = sensors.push(("pms_b", recoveries.pms_b));
+    }
+    if CONFIG.sensor.has_s8() {
+        let _ = sensors.push(("s8", recoveries.s8));
+    }
+    if CONFIG.sensor.has_scd4x() {
+        let _ = sensors.push(("scd", recoveries.scd));
+    }
+    if s.bme_chip.is_some() {
+        let _ = sensors.push(("bme", recoveries.bme));
+    }
+    for (name, count) in sensors {
+        let mut lbl: heapless::String<32> = heapless::String::new();
+        let _ = write!(lbl, "sensor=\"{}\"", name);
+        let _ = mf.write_counter_sample("airgradient_sensor_recoveries", count, Some(&lbl));
+    }
 
     let _ = writeln!(output, "# EOF");
 
@@ -0,0 +1,332 @@
+//! Bosch BME280 (pressure, temperature, humidity) and BMP280 (pressure,
+//! temperature) driver.
+//!
+//! Measurements are taken in forced mode with 1x oversampling and the IIR
+//! filter off, Bosch's recommended settings for weather monitoring. The
+//! compensation formulas are the integer ones from the datasheets.
+
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+
+// SDO to GND; 0x77 with SDO to VDDIO.
+const BME280_ADDRESS: u8 = 0x76;
+
+// Registers
+const REG_CALIB_T_P: u8 = 0x88;
+const REG_CHIP_ID: u8 = 0xD0;
+const REG_RESET: u8 = 0xE0;
+const REG_CALIB_H: u8 = 0xE1;
+const REG_CTRL_HUM: u8 = 0xF2;
+const REG_STATUS: u8 = 0xF3;
+const REG_CTRL_MEAS: u8 = 0xF4;
+const REG_DATA: u8 = 0xF7;
+
+const CHIP_ID_BMP280: u8 = 0x58;
+const CHIP_ID_BME280: u8 = 0x60;
+const RESET_VALUE: u8 = 0xB6;
+const STATUS_MEASURING: u8 = 1 << 3;
+const STATUS_IM_UPDATE: u8 = 1 << 0;
+// 1x oversampling of temperature and pressure, forced mode.
+const CTRL_MEAS_FORCED: u8 = (0b001 << 5) | (0b001 << 2) | 0b01;
+const CTRL_HUM_OVERSAMPLING_1X: u8 = 0b001;
+
+// Timing, from the datasheet
+const STARTUP_DELAY_MS: u64 = 2;
+const MEASURE_DELAY_MS: u64 = 10; // Maximum with 1x oversampling: 9.3 ms
+const MAX_STATUS_POLLS: u8 = 5;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Buffer sizes
+const CALIB_T_P_SIZE: usize = 26; // 0x88..=0xA1, including dig_H1 at 0xA1
+const CALIB_H_SIZE: usize = 7; // 0xE1..=0xE7
+const DATA_SIZE: usize = 8; // pressure, temperature (3 bytes each), humidity (2)
+
+/// Which chip answered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bme280Chip {
+    Bme280,
+    /// No humidity sensor.
+    Bmp280,
+}
+
+impl Bme280Chip {
+    pub fn name(self) -> &'static str {
+        match self {
+            Bme280Chip::Bme280 => "bme280",
+            Bme280Chip::Bmp280 => "bmp280",
+        }
+    }
+}
+
+/// One reading.
+#[derive(Debug, Copy, Clone)]
+pub struct Bme280Measurement {
+    /// Pa.
+    pub pressure: f32,
+    /// °C.
+    pub temperature: f32,
+    /// %RH; `None` on the BMP280.
+    pub humidity: Option<f32>,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Calibration {
+    t1: u16,
+    t2: i16,
+    t3: i16,
+    p1: u16,
+    p2: i16,
+    p3: i16,
+    p4: i16,
+    p5: i16,
+    p6: i16,
+    p7: i16,
+    p8: i16,
+    p9: i16,
+    h1: u8,
+    h2: i16,
+    h3: u8,
+    h4: i16,
+    h5: i16,
+    h6: i8,
+}
+
+pub struct Bme280<I2C> {
+    i2c: I2C,
+    address: u8,
+    timeout: Duration,
+    /// Chip and calibration, once `init` has succeeded.
+    chip: Option<(Bme280Chip, Calibration)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Bme280Error {
+    I2cError,
+    /// The chip ID register held something other than a BME280 or BMP280.
+    UnknownChip(u8),
+    NotInitialized,
+    /// The measurement didn't finish in time.
+    NotReady,
+    Timeout,
+}
+
+impl<I2C> Bme280<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Create a new BME280/BMP280 driver instance
+    ///
+    /// Note: You must call `init()` before taking measurements
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            address: BME280_ADDRESS,
+            timeout: DEFAULT_TIMEOUT,
+            chip: None,
+        }
+    }
+
+    /// Use a different I2C address, e.g. 0x77 with SDO pulled high
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set how long a single I2C transfer may take before failing with
+    /// `Bme280Error::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Identify the chip, reset it and read its calibration
+    pub async fn init(&mut self) -> Result<Bme280Chip, Bme280Error> {
+        self.chip = None;
+        let mut id = [0u8; 1];
+        self.read_registers(REG_CHIP_ID, &mut id).await?;
+        let chip = match id[0] {
+            CHIP_ID_BME280 => Bme280Chip::Bme280,
+            CHIP_ID_BMP280 => Bme280Chip::Bmp280,
+            other => return Err(Bme280Error::UnknownChip(other)),
+        };
+
+        self.write_register(REG_RESET, RESET_VALUE).await?;
+        Timer::after_millis(STARTUP_DELAY_MS).await;
+        // Wait for the calibration to be copied out of NVM.
+        let mut polls = 0;
+        while self.status().await? & STATUS_IM_UPDATE != 0 {
+            polls += 1;
+            if polls >= MAX_STATUS_POLLS {
+                return Err(Bme280Error::NotReady);
+            }
+            Timer::after_millis(STARTUP_DELAY_MS).await;
+        }
+
+        let calibration = self.read_calibration(chip).await?;
+        self.chip = Some((chip, calibration));
+        Ok(chip)
+    }
+
+    /// Chip found by `init`
+    pub fn chip(&self) -> Option<Bme280Chip> {
+        self.chip.map(|(chip, _)| chip)
+    }
+
+    /// Take a forced-mode measurement
+    pub async fn measure(&mut self) -> Result<Bme280Measurement, Bme280Error> {
+        let (chip, calibration) = self.chip.ok_or(Bme280Error::NotInitialized)?;
+
+        if chip == Bme280Chip::Bme280 {
+            // Takes effect with the following write to ctrl_meas.
+            self.write_register(REG_CTRL_HUM, CTRL_HUM_OVERSAMPLING_1X)
+                .await?;
+        }
+        self.write_register(REG_CTRL_MEAS, CTRL_MEAS_FORCED).await?;
+
+        Timer::after_millis(MEASURE_DELAY_MS).await;
+        let mut polls = 0;
+        while self.status().await? & STATUS_MEASURING != 0 {
+            polls += 1;
+            if polls >= MAX_STATUS_POLLS {
+                return Err(Bme280Error::NotReady);
+            }
+            Timer::after_millis(STARTUP_DELAY_MS).await;
+        }
+
+        let mut data = [0u8; DATA_SIZE];
+        let len = match chip {
+            Bme280Chip::Bme280 => DATA_SIZE,
+            Bme280Chip::Bmp280 => DATA_SIZE - 2,
+        };
+        self.read_registers(REG_DATA, &mut data[..len]).await?;
+
+        let adc_p = (data[0] as i32) << 12 | (data[1] as i32) << 4 | (data[2] as i32) >> 4;
+        let adc_t = (data[3] as i32) << 12 | (data[4] as i32) << 4 | (data[5] as i32) >> 4;
+        let adc_h = (data[6] as i32) << 8 | data[7] as i32;
+
+        let (temperature, t_fine) = calibration.temperature(adc_t);
+        Ok(Bme280Measurement {
+            pressure: calibration.pressure(adc_p, t_fine),
+            temperature,
+            humidity: (chip == Bme280Chip::Bme280).then(|| calibration.humidity(adc_h, t_fine)),
+        })
+    }
+
+    async fn read_calibration(&mut self, chip: Bme280Chip) -> Result<Calibration, Bme280Error> {
+        let mut buf = [0u8; CALIB_T_P_SIZE];
+        self.read_registers(REG_CALIB_T_P, &mut buf).await?;
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([buf[i], buf[i + 1]]);
+        let mut calibration = Calibration {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p1: u16_at(6),
+            p2: i16_at(8),
+            p3: i16_at(10),
+            p4: i16_at(12),
+            p5: i16_at(14),
+            p6: i16_at(16),
+            p7: i16_at(18),
+            p8: i16_at(20),
+            p9: i16_at(22),
+            h1: buf[25],
+            ..Calibration::default()
+        };
+
+        if chip == Bme280Chip::Bme280 {
+            let mut h = [0u8; CALIB_H_SIZE];
+            self.read_registers(REG_CALIB_H, &mut h).await?;
+            calibration.h2 = i16::from_le_bytes([h[0], h[1]]);
+            calibration.h3 = h[2];
+            // 12-bit values sharing 0xE5's nibbles; the MSBs are signed.
+            calibration.h4 = (h[3] as i8 as i16) << 4 | (h[4] & 0x0F) as i16;
+            calibration.h5 = (h[5] as i8 as i16) << 4 | (h[4] >> 4) as i16;
+            calibration.h6 = h[6] as i8;
+        }
+        Ok(calibration)
+    }
+
+    async fn status(&mut self) -> Result<u8, Bme280Error> {
+        let mut status = [0u8; 1];
+        self.read_registers(REG_STATUS, &mut status).await?;
+        Ok(status[0])
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Bme280Error> {
+        with_timeout(
+            self.timeout,
+            self.i2c.write(self.address, &[register, value]),
+        )
+        .await
+        .map_err(|_| Bme280Error::Timeout)?
+        .map_err(|_| Bme280Error::I2cError)
+    }
+
+    async fn read_registers(&mut self, register: u8, buf: &mut [u8]) -> Result<(), Bme280Error> {
+        with_timeout(
+            self.timeout,
+            self.i2c.write_read(self.address, &[register], buf),
+        )
+        .await
+        .map_err(|_| Bme280Error::Timeout)?
+        .map_err(|_| Bme280Error::I2cError)
+    }
+}
+
+impl<I2C: BusRecovery> Bme280<I2C> {
+    /// Clear the I2C bus after repeated failures.
+    pub fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
+
+impl Calibration {
+    /// Temperature in °C, and the fine temperature the other formulas take.
+    fn temperature(&self, adc_t: i32) -> (f32, i32) {
+        let t1 = self.t1 as i32;
+        let var1 = (((adc_t >> 3) - (t1 << 1)) * self.t2 as i32) >> 11;
+        let var2 = (((((adc_t >> 4) - t1) * ((adc_t >> 4) - t1)) >> 12) * self.t3 as i32) >> 14;
+        let t_fine = var1 + var2;
+        let centidegrees = (t_fine * 5 + 128) >> 8;
+        (centidegrees as f32 / 100.0, t_fine)
+    }
+
+    /// Pressure in Pa.
+    fn pressure(&self, adc_p: i32, t_fine: i32) -> f32 {
+        let mut var1 = t_fine as i64 - 128000;
+        let mut var2 = var1 * var1 * self.p6 as i64;
+        var2 += (var1 * self.p5 as i64) << 17;
+        var2 += (self.p4 as i64) << 35;
+        var1 = ((var1 * var1 * self.p3 as i64) >> 8) + ((var1 * self.p2 as i64) << 12);
+        var1 = (((1i64 << 47) + var1) * self.p1 as i64) >> 33;
+        if var1 == 0 {
+            // Avoid dividing by zero with an unset calibration.
+            return 0.0;
+        }
+        let mut p = 1048576 - adc_p as i64;
+        p = (((p << 31) - var2) * 3125) / var1;
+        var1 = (self.p9 as i64 * (p >> 13) * (p >> 13)) >> 25;
+        var2 = (self.p8 as i64 * p) >> 19;
+        p = ((p + var1 + var2) >> 8) + ((self.p7 as i64) << 4);
+        // Q24.8
+        p as f32 / 256.0
+    }
+
+    /// Relative humidity in %.
+    fn humidity(&self, adc_h: i32, t_fine: i32) -> f32 {
+        let v = t_fine - 76800;
+        let mut v = (((adc_h << 14) - ((self.h4 as i32) << 20) - (self.h5 as i32 * v) + 16384)
+            >> 15)
+            * (((((((v * self.h6 as i32) >> 10) * (((v * self.h3 as i32) >> 11) + 32768)) >> 10)
+                + 2097152)
+                * self.h2 as i32
+                + 8192)
+                >> 14);
+        v -= ((((v >> 15) * (v >> 15)) >> 7) * self.h1 as i32) >> 4;
+        let v = v.clamp(0, 419430400);
+        // Q22.10
+        (v >> 12) as f32 / 1024.0
+    }
+}
@@ -0,0 +1,237 @@
+//! Sensor transports that can be reset after repeated failures.
+//!
+//! The drivers time out their own transactions; these wrappers add the
+//! recovery step the sensor manager runs when a sensor keeps failing.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use esp_hal::Async;
+use esp_hal::delay::Delay;
+use esp_hal::gpio::{AnyPin, DriveMode, Flex, InputConfig, OutputConfig, Pin, Pull};
+use esp_hal::i2c::master::{self as i2c, I2c};
+use esp_hal::peripherals::I2C0;
+use esp_hal::uart::{self, Uart};
+use static_cell::StaticCell;
+
+/// A transport that can be brought back to a known state.
+pub trait BusRecovery {
+    fn recover(&mut self);
+}
+
+/// UART that drains its receive buffer and re-applies its configuration on
+/// recovery.
+pub struct RecoverableUart {
+    uart: Uart<'static, Async>,
+    config: uart::Config,
+}
+
+impl RecoverableUart {
+    /// `config` must be the configuration `uart` was created with.
+    pub fn new(uart: Uart<'static, Async>, config: uart::Config) -> Self {
+        Self { uart, config }
+    }
+}
+
+impl BusRecovery for RecoverableUart {
+    fn recover(&mut self) {
+        // Discard whatever is buffered, including after an RX error, which
+        // `read_buffered` reports before returning data.
+        let mut buf = [0u8; 64];
+        while self.uart.read_ready() {
+            if let Ok(0) = self.uart.read_buffered(&mut buf) {
+                break;
+            }
+        }
+        if let Err(e) = self.uart.apply_config(&self.config) {
+            defmt::info!(
+                "UART: Failed to re-apply config: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+    }
+}
+
+impl embedded_io_async::ErrorType for RecoverableUart {
+    type Error = uart::IoError;
+}
+
+impl embedded_io_async::Read for RecoverableUart {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Read::read(&mut self.uart, buf).await
+    }
+}
+
+impl embedded_io_async::Write for RecoverableUart {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io_async::Write::write(&mut self.uart, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io_async::Write::flush(&mut self.uart).await
+    }
+}
+
+// Standard-mode I2C: 5 µs per half clock.
+const BUS_CLEAR_HALF_PERIOD_US: u32 = 5;
+// Enough clocks for a device stuck mid-byte to shift out its remaining bits
+// and the ACK.
+const BUS_CLEAR_CLOCKS: usize = 9;
+
+/// I2C0 master that clears the bus on recovery.
+///
+/// Recovery releases the pins from the peripheral, clocks SCL nine times so a
+/// device holding SDA low finishes its byte, issues a STOP, then re-creates
+/// the driver.
+pub struct RecoverableI2c {
+    /// Only `None` while `recover` runs.
+    i2c: Option<I2c<'static, Async>>,
+    peripheral: I2C0<'static>,
+    sda: AnyPin<'static>,
+    scl: AnyPin<'static>,
+    config: i2c::Config,
+}
+
+impl RecoverableI2c {
+    pub fn new(
+        peripheral: I2C0<'static>,
+        sda: impl Pin + 'static,
+        scl: impl Pin + 'static,
+        config: i2c::Config,
+    ) -> Result<Self, i2c::ConfigError> {
+        let mut this = Self {
+            i2c: None,
+            peripheral,
+            sda: sda.degrade(),
+            scl: scl.degrade(),
+            config,
+        };
+        this.i2c = Some(this.connect()?);
+        Ok(this)
+    }
+
+    fn connect(&self) -> Result<I2c<'static, Async>, i2c::ConfigError> {
+        // SAFETY: The previous driver has been dropped, so nothing else
+        // drives the peripheral or the pins.
+        let (peripheral, sda, scl) = unsafe {
+            (
+                self.peripheral.clone_unchecked(),
+                self.sda.clone_unchecked(),
+                self.scl.clone_unchecked(),
+            )
+        };
+        Ok(I2c::new(peripheral, self.config)?
+            .with_sda(sda)
+            .with_scl(scl)
+            .into_async())
+    }
+
+    fn i2c(&mut self) -> &mut I2c<'static, Async> {
+        self.i2c
+            .as_mut()
+            .expect("I2C driver is present outside recover()")
+    }
+
+    fn clear_bus(&self) {
+        // SAFETY: Called with the I2C driver dropped, so the pins are unused.
+        let (sda, scl) = unsafe { (self.sda.clone_unchecked(), self.scl.clone_unchecked()) };
+        let open_drain = OutputConfig::default()
+            .with_drive_mode(DriveMode::OpenDrain)
+            .with_pull(Pull::Up);
+        let delay = Delay::new();
+        let mut sda = Flex::new(sda);
+        let mut scl = Flex::new(scl);
+        for pin in [&mut sda, &mut scl] {
+            pin.set_high();
+            pin.apply_output_config(&open_drain);
+            pin.apply_input_config(&InputConfig::default().with_pull(Pull::Up));
+            pin.set_input_enable(true);
+            pin.set_output_enable(true);
+        }
+
+        for _ in 0..BUS_CLEAR_CLOCKS {
+            if sda.is_high() {
+                break;
+            }
+            scl.set_low();
+            delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+            scl.set_high();
+            delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+        }
+
+        // STOP: SDA rises while SCL is high.
+        scl.set_low();
+        delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+        sda.set_low();
+        delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+        scl.set_high();
+        delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+        sda.set_high();
+        delay.delay_micros(BUS_CLEAR_HALF_PERIOD_US);
+    }
+}
+
+impl BusRecovery for RecoverableI2c {
+    fn recover(&mut self) {
+        // Dropping the driver disconnects the pins from the peripheral.
+        drop(self.i2c.take());
+        self.clear_bus();
+        match self.connect() {
+            Ok(i2c) => self.i2c = Some(i2c),
+            // The config was accepted at boot, so this shouldn't happen.
+            Err(e) => panic!("I2C: Failed to reconnect: {:?}", e),
+        }
+    }
+}
+
+impl embedded_hal_async::i2c::ErrorType for RecoverableI2c {
+    type Error = i2c::Error;
+}
+
+impl embedded_hal_async::i2c::I2c for RecoverableI2c {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        embedded_hal_async::i2c::I2c::transaction(self.i2c(), address, operations).await
+    }
+}
+
+/// Handle to the I2C0 bus for each of the sensors on it.
+///
+/// Transactions take turns through a mutex; recovery clears the bus for all
+/// of them.
+#[derive(Clone, Copy)]
+pub struct SharedI2c(&'static Mutex<CriticalSectionRawMutex, RecoverableI2c>);
+
+impl SharedI2c {
+    /// Must be called only once.
+    pub fn new(i2c: RecoverableI2c) -> Self {
+        static BUS: StaticCell<Mutex<CriticalSectionRawMutex, RecoverableI2c>> = StaticCell::new();
+        Self(BUS.init(Mutex::new(i2c)))
+    }
+}
+
+impl BusRecovery for SharedI2c {
+    fn recover(&mut self) {
+        // The sensors are driven from one task, so the bus is idle here.
+        match self.0.try_lock() {
+            Ok(mut i2c) => i2c.recover(),
+            Err(_) => defmt::info!("I2C: Bus busy, skipping recovery"),
+        }
+    }
+}
+
+impl embedded_hal_async::i2c::ErrorType for SharedI2c {
+    type Error = i2c::Error;
+}
+
+impl embedded_hal_async::i2c::I2c for SharedI2c {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.0.lock().await.transaction(address, operations).await
+    }
+}
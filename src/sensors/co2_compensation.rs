@@ -0,0 +1,41 @@
+//! Barometric pressure compensation of NDIR CO2 readings.
+//!
+//! An NDIR sensor measures CO2 molecules in its optical path, so at a fixed
+//! mixing ratio its reading rises with pressure. SenseAir specifies the S8's
+//! dependence as 1.6% of reading per kPa away from the 100 kPa it is
+//! calibrated at.
+//!
+//! Without a barometer, a fixed pressure can be derived from the site's
+//! altitude with the standard atmosphere's barometric formula.
+
+use micromath::F32Ext;
+
+/// Pressure the S8 is calibrated at, in Pa.
+pub const S8_REFERENCE_PRESSURE_PA: f32 = 100_000.0;
+// Fraction of reading per Pa.
+const S8_PRESSURE_DEPENDENCE: f32 = 0.016 / 1000.0;
+// International Standard Atmosphere, troposphere.
+const SEA_LEVEL_PRESSURE_PA: f32 = 101_325.0;
+const LAPSE_RATE_PER_M: f32 = 2.25577e-5;
+const BAROMETRIC_EXPONENT: f32 = 5.25588;
+// The linear model breaks down far from the reference; 50 kPa is about
+// 5.5 km up, 110 kPa the top of the BME280/BMP280 range.
+const MIN_PRESSURE_PA: f32 = 50_000.0;
+const MAX_PRESSURE_PA: f32 = 110_000.0;
+
+/// Factor that corrects an S8 reading taken at `pressure_pa`.
+pub fn s8_factor(pressure_pa: f32) -> f32 {
+    let pressure = pressure_pa.clamp(MIN_PRESSURE_PA, MAX_PRESSURE_PA);
+    1.0 / (1.0 + S8_PRESSURE_DEPENDENCE * (pressure - S8_REFERENCE_PRESSURE_PA))
+}
+
+/// Apply a factor from `s8_factor` to a reading in ppm.
+pub fn apply(co2_ppm: u16, factor: f32) -> u16 {
+    let compensated = co2_ppm as f32 * factor + 0.5;
+    compensated.clamp(0.0, u16::MAX as f32) as u16
+}
+
+/// Typical pressure in Pa at `meters` above sea level.
+pub fn pressure_at_altitude(meters: f32) -> f32 {
+    SEA_LEVEL_PRESSURE_PA * (1.0 - LAPSE_RATE_PER_M * meters).powf(BAROMETRIC_EXPONENT)
+}
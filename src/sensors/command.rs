@@ -0,0 +1,133 @@
+//! Requests from other tasks (e.g. the web server) to the sensor task, which
+//! owns the sensor drivers.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use static_cell::StaticCell;
+
+use crate::sensors::gas_index::{AlgorithmType, TuningParameters};
+use crate::sensors::s8::S8Error;
+use crate::sensors::scd4x::Scd4xError;
+use crate::sensors::sen5x::Sen5xError;
+use crate::sensors::sgp41::Sgp41Error;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SensorCommand {
+    /// Run a SenseAir S8 background (400 ppm) calibration.
+    CalibrateS8,
+    /// Run an SCD4x forced recalibration to `target_ppm`.
+    CalibrateScd4x { target_ppm: u16 },
+    /// Run a SEN5x/SEN66 fan cleaning.
+    CleanSen5xFan,
+    /// Run the SGP41 self-test, then condition the sensor again.
+    SelfTestSgp41,
+    /// Change SGP41 gas index tuning parameters, restarting that index's
+    /// learning.
+    TuneGasIndex {
+        algorithm: AlgorithmType,
+        update: TuningUpdate,
+    },
+}
+
+/// Tuning parameters to change; `None` keeps the current value.
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+pub struct TuningUpdate {
+    pub index_offset: Option<i32>,
+    pub learning_time_offset_hours: Option<i32>,
+    pub learning_time_gain_hours: Option<i32>,
+    pub gating_max_duration_minutes: Option<i32>,
+    pub std_initial: Option<i32>,
+    pub gain_factor: Option<i32>,
+}
+
+impl TuningUpdate {
+    pub fn apply(&self, current: TuningParameters) -> TuningParameters {
+        TuningParameters {
+            index_offset: self.index_offset.unwrap_or(current.index_offset),
+            learning_time_offset_hours: self
+                .learning_time_offset_hours
+                .unwrap_or(current.learning_time_offset_hours),
+            learning_time_gain_hours: self
+                .learning_time_gain_hours
+                .unwrap_or(current.learning_time_gain_hours),
+            gating_max_duration_minutes: self
+                .gating_max_duration_minutes
+                .unwrap_or(current.gating_max_duration_minutes),
+            std_initial: self.std_initial.unwrap_or(current.std_initial),
+            gain_factor: self.gain_factor.unwrap_or(current.gain_factor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SensorCommandError {
+    /// The sensor the command targets is not fitted on this model.
+    NotFitted,
+    /// Parameters outside the documented ranges.
+    InvalidParameters,
+    /// The sensor can't take the command right now; try again later.
+    Busy,
+    S8(S8Error),
+    Scd4x(Scd4xError),
+    Sen5x(Sen5xError),
+    Sgp41(Sgp41Error),
+}
+
+pub type SensorCommandResult = Result<(), SensorCommandError>;
+
+/// Identifies a command so its response can't be taken for another's.
+pub(crate) type RequestId = u32;
+
+struct Inner {
+    /// Serializes callers and holds the next request id.
+    lock: Mutex<CriticalSectionRawMutex, RequestId>,
+    requests: Channel<CriticalSectionRawMutex, (RequestId, SensorCommand), 1>,
+    responses: Signal<CriticalSectionRawMutex, (RequestId, SensorCommandResult)>,
+}
+
+#[derive(Clone, Copy)]
+pub struct SensorCommands(&'static Inner);
+
+impl SensorCommands {
+    pub fn new() -> Self {
+        static COMMANDS: StaticCell<Inner> = StaticCell::new();
+        Self(COMMANDS.init(Inner {
+            lock: Mutex::new(0),
+            requests: Channel::new(),
+            responses: Signal::new(),
+        }))
+    }
+
+    /// Send a command to the sensor task and wait for its result.
+    ///
+    /// A caller that gave up waiting may leave its command running; its
+    /// response is skipped by the next caller.
+    pub async fn execute(&self, command: SensorCommand) -> SensorCommandResult {
+        let mut next_id = self.0.lock.lock().await;
+        let id = *next_id;
+        *next_id = id.wrapping_add(1);
+        self.0.requests.send((id, command)).await;
+        loop {
+            let (responded, result) = self.0.responses.wait().await;
+            if responded == id {
+                return result;
+            }
+        }
+    }
+
+    pub(crate) async fn receive(&self) -> (RequestId, SensorCommand) {
+        self.0.requests.receive().await
+    }
+
+    pub(crate) fn respond(&self, id: RequestId, result: SensorCommandResult) {
+        self.0.responses.signal((id, result));
+    }
+}
+
+impl Default for SensorCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,534 @@
+//! Sensirion Gas Index Algorithm (VOC and NOx index) v3.2.
+//!
+//! Ported from the `gas-index-algorithm` crate (itself a port of Sensirion's C
+//! implementation), adding the parts of the C API it lacks: `get_states` /
+//! `set_states` for persisting the learned baseline across reboots.
+//!
+//! Copyright (c) 2021, Sensirion AG. BSD 3-Clause License.
+
+use micromath::F32Ext;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AlgorithmType {
+    Voc,
+    Nox,
+}
+
+/// Mean and standard deviation of the raw signal as learned by the
+/// algorithm, as returned by Sensirion's `GasIndexAlgorithm_get_states`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GasIndexStates {
+    pub mean: f32,
+    pub std: f32,
+}
+
+/// Internal state of the algorithm, for debugging and offline reprocessing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GasIndexInternals {
+    /// Estimated mean of the raw signal, in ticks.
+    pub mean: f32,
+    /// Estimated standard deviation of the raw signal, in ticks.
+    pub std: f32,
+    /// Accumulated time learning has been gated by a high index, in minutes.
+    /// Gating is lifted once this exceeds the gating max duration.
+    pub gating_duration_minutes: f32,
+    /// Learning time the estimator has seen, in seconds. Saturates at about
+    /// 9 h and is set to 3 h by `set_states`.
+    pub learning_secs: f32,
+}
+
+/// Sensirion's tuning parameters, as passed to
+/// `GasIndexAlgorithm_set_tuning_parameters`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TuningParameters {
+    /// Index representing typical (average) conditions. 1..=250.
+    pub index_offset: i32,
+    /// Time constant of the long-term estimator for the offset, in hours.
+    /// Past events are forgotten after about twice this. 1..=1000.
+    pub learning_time_offset_hours: i32,
+    /// Time constant of the long-term estimator for the gain, in hours.
+    /// 1..=1000; NOx only supports the default.
+    pub learning_time_gain_hours: i32,
+    /// Longest time the estimator may freeze during a high-index event; 0
+    /// disables gating. 0..=3000.
+    pub gating_max_duration_minutes: i32,
+    /// Initial estimate for the standard deviation. 10..=5000; NOx only
+    /// supports the default.
+    pub std_initial: i32,
+    /// Gain applied to the index after learning. 1..=1000.
+    pub gain_factor: i32,
+}
+
+impl TuningParameters {
+    pub const VOC_DEFAULT: Self = Self {
+        index_offset: 100,
+        learning_time_offset_hours: 12,
+        learning_time_gain_hours: 12,
+        gating_max_duration_minutes: 180,
+        std_initial: 50,
+        gain_factor: 230,
+    };
+
+    pub const NOX_DEFAULT: Self = Self {
+        index_offset: 1,
+        learning_time_offset_hours: 12,
+        learning_time_gain_hours: 12,
+        gating_max_duration_minutes: 720,
+        std_initial: 50,
+        gain_factor: 230,
+    };
+
+    pub const fn default_for(algorithm_type: AlgorithmType) -> Self {
+        match algorithm_type {
+            AlgorithmType::Voc => Self::VOC_DEFAULT,
+            AlgorithmType::Nox => Self::NOX_DEFAULT,
+        }
+    }
+
+    /// Whether the parameters are within the ranges Sensirion documents.
+    pub const fn is_valid(&self, algorithm_type: AlgorithmType) -> bool {
+        const fn within(value: i32, min: i32, max: i32) -> bool {
+            min <= value && value <= max
+        }
+        let default = Self::default_for(algorithm_type);
+        within(self.index_offset, 1, 250)
+            && within(self.learning_time_offset_hours, 1, 1000)
+            && within(self.learning_time_gain_hours, 1, 1000)
+            && within(self.gating_max_duration_minutes, 0, 3000)
+            && within(self.std_initial, 10, 5000)
+            && within(self.gain_factor, 1, 1000)
+            && (matches!(algorithm_type, AlgorithmType::Voc)
+                || (self.learning_time_gain_hours == default.learning_time_gain_hours
+                    && self.std_initial == default.std_initial))
+    }
+}
+
+// Learning time before the states are worth saving, per Sensirion.
+const STATES_MIN_UPTIME_SECS: f32 = 3.0 * 3600.0;
+// Uptime assumed for the estimator when states are restored.
+const PERSISTENCE_UPTIME_GAMMA: f32 = 3.0 * 3600.0;
+// Output is held at zero for this long after (re)initialization.
+const INITIAL_BLACKOUT_SECS: f32 = 45.0;
+
+#[derive(Debug)]
+pub struct GasIndexAlgorithm {
+    algorithm_type: AlgorithmType,
+    sampling_interval: f32,
+    tuning: TuningParameters,
+    index_offset: f32,
+    sraw_minimum: i32,
+    gating_max_duration_minutes: f32,
+    init_duration_mean: f32,
+    init_duration_variance: f32,
+    gating_threshold: f32,
+    index_gain: f32,
+    tau_mean_hours: f32,
+    tau_variance_hours: f32,
+    sraw_std_initial: f32,
+    uptime: f32,
+    sraw: f32,
+    gas_index: f32,
+    mean_variance_estimator_initialized: bool,
+    mean_variance_estimator_mean: f32,
+    mean_variance_estimator_sraw_offset: f32,
+    mean_variance_estimator_std: f32,
+    mean_variance_estimator_gamma_mean: f32,
+    mean_variance_estimator_gamma_variance: f32,
+    mean_variance_estimator_gamma_initial_mean: f32,
+    mean_variance_estimator_gamma_initial_variance: f32,
+    mean_variance_estimator_gamma_mean2: f32,
+    mean_variance_estimator_gamma_variance2: f32,
+    mean_variance_estimator_uptime_gamma: f32,
+    mean_variance_estimator_uptime_gating: f32,
+    mean_variance_estimator_gating_duration_minutes: f32,
+    mean_variance_estimator_sigmoid_k: f32,
+    mean_variance_estimator_sigmoid_x0: f32,
+    mox_model_sraw_std: f32,
+    mox_model_sraw_mean: f32,
+    sigmoid_scaled_k: f32,
+    sigmoid_scaled_x0: f32,
+    sigmoid_scaled_offset_default: f32,
+    adaptive_lowpass_a1: f32,
+    adaptive_lowpass_a2: f32,
+    adaptive_lowpass_initialized: bool,
+    adaptive_lowpass_x1: f32,
+    adaptive_lowpass_x2: f32,
+    adaptive_lowpass_x3: f32,
+}
+
+impl GasIndexAlgorithm {
+    pub fn new(algorithm_type: AlgorithmType, sampling_interval: f32) -> Self {
+        Self::with_tuning_parameters(
+            algorithm_type,
+            sampling_interval,
+            TuningParameters::default_for(algorithm_type),
+        )
+    }
+
+    pub fn with_tuning_parameters(
+        algorithm_type: AlgorithmType,
+        sampling_interval: f32,
+        tuning: TuningParameters,
+    ) -> Self {
+        let (sraw_minimum, init_duration_mean, init_duration_variance, gating_threshold) =
+            match algorithm_type {
+                AlgorithmType::Nox => (10000, 3600.0 * 4.75, 3600.0 * 5.70, 30.0),
+                AlgorithmType::Voc => (20000, 3600.0 * 0.75, 3600.0 * 1.45, 340.0),
+            };
+
+        let mut algorithm = Self {
+            algorithm_type,
+            sampling_interval,
+            tuning,
+            index_offset: 0.0,
+            sraw_minimum,
+            gating_max_duration_minutes: 0.0,
+            init_duration_mean,
+            init_duration_variance,
+            gating_threshold,
+            index_gain: 0.0,
+            tau_mean_hours: 0.0,
+            tau_variance_hours: 0.0,
+            sraw_std_initial: 0.0,
+            uptime: 0.0,
+            sraw: 0.0,
+            gas_index: 0.0,
+            mean_variance_estimator_initialized: false,
+            mean_variance_estimator_mean: 0.0,
+            mean_variance_estimator_sraw_offset: 0.0,
+            mean_variance_estimator_std: 0.0,
+            mean_variance_estimator_gamma_mean: 0.0,
+            mean_variance_estimator_gamma_variance: 0.0,
+            mean_variance_estimator_gamma_initial_mean: 0.0,
+            mean_variance_estimator_gamma_initial_variance: 0.0,
+            mean_variance_estimator_gamma_mean2: 0.0,
+            mean_variance_estimator_gamma_variance2: 0.0,
+            mean_variance_estimator_uptime_gamma: 0.0,
+            mean_variance_estimator_uptime_gating: 0.0,
+            mean_variance_estimator_gating_duration_minutes: 0.0,
+            mean_variance_estimator_sigmoid_k: 0.0,
+            mean_variance_estimator_sigmoid_x0: 0.0,
+            mox_model_sraw_std: 0.0,
+            mox_model_sraw_mean: 0.0,
+            sigmoid_scaled_k: 0.0,
+            sigmoid_scaled_x0: 0.0,
+            sigmoid_scaled_offset_default: 0.0,
+            adaptive_lowpass_a1: 0.0,
+            adaptive_lowpass_a2: 0.0,
+            adaptive_lowpass_initialized: false,
+            adaptive_lowpass_x1: 0.0,
+            adaptive_lowpass_x2: 0.0,
+            adaptive_lowpass_x3: 0.0,
+        };
+        algorithm.set_tuning_parameters(tuning);
+        algorithm
+    }
+
+    pub fn tuning_parameters(&self) -> TuningParameters {
+        self.tuning
+    }
+
+    /// Apply new tuning parameters.
+    ///
+    /// As in Sensirion's implementation, this restarts learning.
+    pub fn set_tuning_parameters(&mut self, tuning: TuningParameters) {
+        self.tuning = tuning;
+        self.index_offset = tuning.index_offset as f32;
+        self.tau_mean_hours = tuning.learning_time_offset_hours as f32;
+        self.tau_variance_hours = tuning.learning_time_gain_hours as f32;
+        self.gating_max_duration_minutes = tuning.gating_max_duration_minutes as f32;
+        self.sraw_std_initial = tuning.std_initial as f32;
+        self.index_gain = tuning.gain_factor as f32;
+        self.init_instances();
+    }
+
+    pub fn algorithm_type(&self) -> AlgorithmType {
+        self.algorithm_type
+    }
+
+    /// Calculate the gas index value from the raw sensor value.
+    ///
+    /// Returns the calculated gas index value from the raw sensor value.
+    /// Zero during initial blackout period and 1..500 afterwards.
+    pub fn process(&mut self, mut sraw: i32) -> i32 {
+        if self.uptime <= INITIAL_BLACKOUT_SECS {
+            self.uptime += self.sampling_interval;
+        } else {
+            if sraw > 0 && sraw < 65000 {
+                if sraw < self.sraw_minimum + 1 {
+                    sraw = self.sraw_minimum + 1;
+                } else if sraw > self.sraw_minimum + 32767 {
+                    sraw = self.sraw_minimum + 32767;
+                }
+                self.sraw = (sraw - self.sraw_minimum) as f32;
+            }
+            if self.algorithm_type == AlgorithmType::Voc || self.mean_variance_estimator_initialized
+            {
+                self.gas_index = self.mox_model_process(self.sraw);
+                self.gas_index = self.sigmoid_scaled_process(self.gas_index);
+            } else {
+                self.gas_index = self.index_offset;
+            }
+            self.gas_index = self.adaptive_lowpass_process(self.gas_index);
+            if self.gas_index < 0.5 {
+                self.gas_index = 0.5;
+            }
+            if self.sraw > 0.0 {
+                self.mean_variance_estimator_process(self.sraw);
+                self.mox_model_set_parameters(
+                    self.mean_variance_estimator_std,
+                    self.mean_variance_estimator_get_mean(),
+                );
+            }
+        }
+        (self.gas_index + 0.5) as i32
+    }
+
+    pub fn internals(&self) -> GasIndexInternals {
+        GasIndexInternals {
+            mean: self.mean_variance_estimator_get_mean(),
+            std: self.mean_variance_estimator_std,
+            gating_duration_minutes: self.mean_variance_estimator_gating_duration_minutes,
+            learning_secs: self.mean_variance_estimator_uptime_gamma,
+        }
+    }
+
+    /// Whether the algorithm has learned for long enough (3 h, or since
+    /// `set_states`) for `get_states` to be worth saving.
+    pub fn states_learned(&self) -> bool {
+        self.mean_variance_estimator_initialized
+            && self.mean_variance_estimator_uptime_gamma >= STATES_MIN_UPTIME_SECS
+    }
+
+    /// The learned baseline, for restoring with `set_states` after a short
+    /// interruption.
+    ///
+    /// Per Sensirion, only meaningful for the VOC algorithm.
+    pub fn get_states(&self) -> GasIndexStates {
+        GasIndexStates {
+            mean: self.mean_variance_estimator_get_mean(),
+            std: self.mean_variance_estimator_std,
+        }
+    }
+
+    /// Restore a baseline saved with `get_states`, skipping the initial
+    /// learning phase.
+    pub fn set_states(&mut self, states: GasIndexStates) {
+        self.mean_variance_estimator_mean = states.mean;
+        self.mean_variance_estimator_sraw_offset = 0.0;
+        self.mean_variance_estimator_std = states.std;
+        self.mean_variance_estimator_uptime_gamma = PERSISTENCE_UPTIME_GAMMA;
+        self.mean_variance_estimator_initialized = true;
+        self.mox_model_set_parameters(states.std, states.mean);
+        self.sraw = states.mean;
+    }
+
+    fn init_instances(&mut self) {
+        self.mean_variance_estimator_set_parameters();
+        self.mox_model_set_parameters(
+            self.mean_variance_estimator_std,
+            self.mean_variance_estimator_get_mean(),
+        );
+        match self.algorithm_type {
+            AlgorithmType::Nox => self.sigmoid_scaled_set_parameters(614.0, -0.0101, 1.0),
+            AlgorithmType::Voc => self.sigmoid_scaled_set_parameters(213.0, -0.0065, 100.0),
+        }
+        self.adaptive_lowpass_set_parameters();
+    }
+
+    fn mean_variance_estimator_set_parameters(&mut self) {
+        self.mean_variance_estimator_initialized = false;
+        self.mean_variance_estimator_mean = 0.0;
+        self.mean_variance_estimator_sraw_offset = 0.0;
+        self.mean_variance_estimator_std = self.sraw_std_initial;
+        self.mean_variance_estimator_gamma_mean = 8.0 * 64.0 * (self.sampling_interval / 3600.0)
+            / (self.tau_mean_hours + self.sampling_interval / 3600.0);
+        self.mean_variance_estimator_gamma_variance = 64.0 * (self.sampling_interval / 3600.0)
+            / (self.tau_variance_hours + self.sampling_interval / 3600.0);
+        let initial_mean_tau = match self.algorithm_type {
+            AlgorithmType::Nox => 1200.0,
+            AlgorithmType::Voc => 20.0,
+        };
+        self.mean_variance_estimator_gamma_initial_mean =
+            8.0 * 64.0 * self.sampling_interval / (initial_mean_tau + self.sampling_interval);
+        self.mean_variance_estimator_gamma_initial_variance =
+            64.0 * self.sampling_interval / (2500.0 + self.sampling_interval);
+        self.mean_variance_estimator_gamma_mean2 = 0.0;
+        self.mean_variance_estimator_gamma_variance2 = 0.0;
+        self.mean_variance_estimator_uptime_gamma = 0.0;
+        self.mean_variance_estimator_uptime_gating = 0.0;
+        self.mean_variance_estimator_gating_duration_minutes = 0.0;
+    }
+
+    fn mean_variance_estimator_get_mean(&self) -> f32 {
+        self.mean_variance_estimator_mean + self.mean_variance_estimator_sraw_offset
+    }
+
+    fn mean_variance_estimator_calculate_gamma(&mut self) {
+        let uptime_limit = 32767.0 - self.sampling_interval;
+        if self.mean_variance_estimator_uptime_gamma < uptime_limit {
+            self.mean_variance_estimator_uptime_gamma += self.sampling_interval;
+        }
+        if self.mean_variance_estimator_uptime_gating < uptime_limit {
+            self.mean_variance_estimator_uptime_gating += self.sampling_interval;
+        }
+        self.mean_variance_estimator_sigmoid_set_parameters(self.init_duration_mean, 0.01);
+        let sigmoid_gamma_mean =
+            self.mean_variance_estimator_sigmoid_process(self.mean_variance_estimator_uptime_gamma);
+        let gamma_mean = self.mean_variance_estimator_gamma_mean
+            + (self.mean_variance_estimator_gamma_initial_mean
+                - self.mean_variance_estimator_gamma_mean)
+                * sigmoid_gamma_mean;
+        let gating_threshold_mean = self.gating_threshold
+            + (510.0 - self.gating_threshold)
+                * self.mean_variance_estimator_sigmoid_process(
+                    self.mean_variance_estimator_uptime_gating,
+                );
+        self.mean_variance_estimator_sigmoid_set_parameters(gating_threshold_mean, 0.09);
+        let sigmoid_gating_mean = self.mean_variance_estimator_sigmoid_process(self.gas_index);
+        self.mean_variance_estimator_gamma_mean2 = sigmoid_gating_mean * gamma_mean;
+        self.mean_variance_estimator_sigmoid_set_parameters(self.init_duration_variance, 0.01);
+        let sigmoid_gamma_variance =
+            self.mean_variance_estimator_sigmoid_process(self.mean_variance_estimator_uptime_gamma);
+        let gamma_variance = self.mean_variance_estimator_gamma_variance
+            + (self.mean_variance_estimator_gamma_initial_variance
+                - self.mean_variance_estimator_gamma_variance)
+                * (sigmoid_gamma_variance - sigmoid_gamma_mean);
+        let gating_threshold_variance = self.gating_threshold
+            + (510.0 - self.gating_threshold)
+                * self.mean_variance_estimator_sigmoid_process(
+                    self.mean_variance_estimator_uptime_gating,
+                );
+        self.mean_variance_estimator_sigmoid_set_parameters(gating_threshold_variance, 0.09);
+        let sigmoid_gating_variance = self.mean_variance_estimator_sigmoid_process(self.gas_index);
+        self.mean_variance_estimator_gamma_variance2 = sigmoid_gating_variance * gamma_variance;
+
+        self.mean_variance_estimator_gating_duration_minutes +=
+            self.sampling_interval / 60.0 * ((1.0 - sigmoid_gating_mean) * (1.0 + 0.3) - 0.3);
+        if self.mean_variance_estimator_gating_duration_minutes < 0.0 {
+            self.mean_variance_estimator_gating_duration_minutes = 0.0;
+        }
+        if self.mean_variance_estimator_gating_duration_minutes > self.gating_max_duration_minutes {
+            self.mean_variance_estimator_uptime_gating = 0.0;
+        }
+    }
+
+    fn mean_variance_estimator_process(&mut self, mut sraw: f32) {
+        if !self.mean_variance_estimator_initialized {
+            self.mean_variance_estimator_initialized = true;
+            self.mean_variance_estimator_sraw_offset = sraw;
+            self.mean_variance_estimator_mean = 0.0;
+            return;
+        }
+
+        if self.mean_variance_estimator_mean >= 100.0 || self.mean_variance_estimator_mean <= -100.0
+        {
+            self.mean_variance_estimator_sraw_offset += self.mean_variance_estimator_mean;
+            self.mean_variance_estimator_mean = 0.0;
+        }
+        sraw -= self.mean_variance_estimator_sraw_offset;
+
+        self.mean_variance_estimator_calculate_gamma();
+        let delta_sgp = (sraw - self.mean_variance_estimator_mean) / 64.0;
+        let c = if delta_sgp < 0.0 {
+            self.mean_variance_estimator_std - delta_sgp
+        } else {
+            self.mean_variance_estimator_std + delta_sgp
+        };
+        let mut additional_scaling = 1.0;
+        if c > 1440.0 {
+            additional_scaling = c / 1440.0 * (c / 1440.0);
+        }
+        self.mean_variance_estimator_std = (additional_scaling
+            * (64.0 - self.mean_variance_estimator_gamma_variance2))
+            .sqrt()
+            * (self.mean_variance_estimator_std
+                * (self.mean_variance_estimator_std / (64.0 * additional_scaling))
+                + self.mean_variance_estimator_gamma_variance2 * delta_sgp / additional_scaling
+                    * delta_sgp)
+                .sqrt();
+        self.mean_variance_estimator_mean +=
+            self.mean_variance_estimator_gamma_mean2 * delta_sgp / 8.0;
+    }
+
+    fn mean_variance_estimator_sigmoid_set_parameters(&mut self, x0: f32, k: f32) {
+        self.mean_variance_estimator_sigmoid_k = k;
+        self.mean_variance_estimator_sigmoid_x0 = x0;
+    }
+
+    fn mean_variance_estimator_sigmoid_process(&self, sample: f32) -> f32 {
+        let x = self.mean_variance_estimator_sigmoid_k
+            * (sample - self.mean_variance_estimator_sigmoid_x0);
+        if x < -50.0 {
+            1.0
+        } else if x > 50.0 {
+            0.0
+        } else {
+            1.0 / (1.0 + x.exp())
+        }
+    }
+
+    fn mox_model_set_parameters(&mut self, sraw_std: f32, sraw_mean: f32) {
+        self.mox_model_sraw_std = sraw_std;
+        self.mox_model_sraw_mean = sraw_mean;
+    }
+
+    fn mox_model_process(&self, sraw: f32) -> f32 {
+        match self.algorithm_type {
+            AlgorithmType::Nox => (sraw - self.mox_model_sraw_mean) / 2000.0 * self.index_gain,
+            AlgorithmType::Voc => {
+                (sraw - self.mox_model_sraw_mean) / -(self.mox_model_sraw_std + 220.0)
+                    * self.index_gain
+            }
+        }
+    }
+
+    fn sigmoid_scaled_set_parameters(&mut self, x0: f32, k: f32, offset_default: f32) {
+        self.sigmoid_scaled_k = k;
+        self.sigmoid_scaled_x0 = x0;
+        self.sigmoid_scaled_offset_default = offset_default;
+    }
+
+    fn sigmoid_scaled_process(&self, sample: f32) -> f32 {
+        let x = self.sigmoid_scaled_k * (sample - self.sigmoid_scaled_x0);
+        if x < -50.0 {
+            500.0
+        } else if x > 50.0 {
+            0.0
+        } else if sample >= 0.0 {
+            let shift = if self.sigmoid_scaled_offset_default == 1.0 {
+                500.0 / 499.0 * (1.0 - self.index_offset)
+            } else {
+                (500.0 - 5.0 * self.index_offset) / 4.0
+            };
+            (500.0 + shift) / (1.0 + x.exp()) - shift
+        } else {
+            self.index_offset / self.sigmoid_scaled_offset_default * (500.0 / (1.0 + x.exp()))
+        }
+    }
+
+    fn adaptive_lowpass_set_parameters(&mut self) {
+        self.adaptive_lowpass_a1 = self.sampling_interval / (20.0 + self.sampling_interval);
+        self.adaptive_lowpass_a2 = self.sampling_interval / (500.0 + self.sampling_interval);
+        self.adaptive_lowpass_initialized = false;
+    }
+
+    fn adaptive_lowpass_process(&mut self, sample: f32) -> f32 {
+        if !self.adaptive_lowpass_initialized {
+            self.adaptive_lowpass_x1 = sample;
+            self.adaptive_lowpass_x2 = sample;
+            self.adaptive_lowpass_x3 = sample;
+            self.adaptive_lowpass_initialized = true;
+        }
+        self.adaptive_lowpass_x1 = (1.0 - self.adaptive_lowpass_a1) * self.adaptive_lowpass_x1
+            + self.adaptive_lowpass_a1 * sample;
+        self.adaptive_lowpass_x2 = (1.0 - self.adaptive_lowpass_a2) * self.adaptive_lowpass_x2
+            + self.adaptive_lowpass_a2 * sample;
+        let abs_delta = (self.adaptive_lowpass_x1 - self.adaptive_lowpass_x2).abs();
+        let f1 = (-0.2 * abs_delta).exp();
+        let tau_a = (500.0 - 20.0) * f1 + 20.0;
+        let a3 = self.sampling_interval / (self.sampling_interval + tau_a);
+        self.adaptive_lowpass_x3 = (1.0 - a3) * self.adaptive_lowpass_x3 + a3 * sample;
+        self.adaptive_lowpass_x3
+    }
+}
@@ -0,0 +1,167 @@
+//! Persistence of the SGP41 VOC gas index baseline across reboots.
+//!
+//! Records are appended round-robin to a dedicated flash partition; the
+//! newest valid record wins. Each record is stamped with the RTC timer, which
+//! keeps running through software and internal watchdog resets, so the age
+//! of a saved state can be told unless the chip lost power.
+
+use embassy_time::Duration;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash, RmwNorFlashStorage};
+use esp_bootloader_esp_idf::partitions;
+use esp_hal::rom::crc::crc32_le;
+use esp_hal::rtc_cntl::Rtc;
+use esp_storage::{FlashStorage, FlashStorageError};
+
+use crate::sensors::gas_index::GasIndexStates;
+
+/// Label of the data partition in `partitions.csv`.
+pub const PARTITION_LABEL: &str = "sgp41";
+
+const SECTOR_SIZE: u32 = FlashStorage::SECTOR_SIZE;
+const MAGIC: u32 = u32::from_le_bytes(*b"GIX1");
+const RECORD_SIZE: u32 = 32;
+// Magic, sequence, saved_at (2 words), mean, std, reserved; CRC follows.
+const CRC_OFFSET: usize = 28;
+
+/// A state loaded from flash.
+#[derive(Debug, Copy, Clone)]
+pub struct SavedStates {
+    pub states: GasIndexStates,
+    /// Time since the state was saved. `None` if the RTC restarted since,
+    /// i.e. after a power-on reset.
+    pub age: Option<Duration>,
+}
+
+pub struct GasIndexStore {
+    flash: FlashStorage<'static>,
+    rtc: Rtc<'static>,
+    offset: u32,
+    slots: u32,
+    next_slot: u32,
+    sequence: u32,
+}
+
+impl GasIndexStore {
+    /// Open the store, or `None` if the flash has no `sgp41` partition.
+    pub fn new(mut flash: FlashStorage<'static>, rtc: Rtc<'static>) -> Option<Self> {
+        let Some((offset, len)) = find_partition(&mut flash, PARTITION_LABEL) else {
+            defmt::info!(
+                "SGP41: No '{}' partition, gas index state won't persist",
+                PARTITION_LABEL
+            );
+            return None;
+        };
+        // Whole sectors only, and at least two so erasing one never loses
+        // the newest record.
+        let len = len - len % SECTOR_SIZE;
+        if !offset.is_multiple_of(SECTOR_SIZE) || len < 2 * SECTOR_SIZE {
+            defmt::info!("SGP41: '{}' partition is too small", PARTITION_LABEL);
+            return None;
+        }
+        Some(Self {
+            flash,
+            rtc,
+            offset,
+            slots: len / RECORD_SIZE,
+            next_slot: 0,
+            sequence: 0,
+        })
+    }
+
+    /// Find the newest valid record.
+    pub fn load(&mut self) -> Option<SavedStates> {
+        let mut newest: Option<(u32, [u8; RECORD_SIZE as usize])> = None;
+        for slot in 0..self.slots {
+            let mut record = [0u8; RECORD_SIZE as usize];
+            if self
+                .flash
+                .read(self.offset + slot * RECORD_SIZE, &mut record)
+                .is_err()
+                || !is_valid(&record)
+            {
+                continue;
+            }
+            let sequence = word(&record, 1);
+            if newest.is_none_or(|(_, r)| sequence.wrapping_sub(word(&r, 1)) as i32 > 0) {
+                newest = Some((slot, record));
+            }
+        }
+
+        let (slot, record) = newest?;
+        self.next_slot = (slot + 1) % self.slots;
+        self.sequence = word(&record, 1).wrapping_add(1);
+
+        let saved_at = (word(&record, 3) as u64) << 32 | word(&record, 2) as u64;
+        let now = self.rtc.time_since_boot().as_micros();
+        Some(SavedStates {
+            states: GasIndexStates {
+                mean: f32::from_bits(word(&record, 4)),
+                std: f32::from_bits(word(&record, 5)),
+            },
+            age: (now >= saved_at).then(|| Duration::from_micros(now - saved_at)),
+        })
+    }
+
+    /// Append a record, erasing the next sector first when entering it.
+    pub fn save(&mut self, states: GasIndexStates) -> Result<(), FlashStorageError> {
+        let address = self.offset + self.next_slot * RECORD_SIZE;
+        if address.is_multiple_of(SECTOR_SIZE) {
+            self.flash.erase(address, address + SECTOR_SIZE)?;
+        }
+
+        let saved_at = self.rtc.time_since_boot().as_micros();
+        let mut record = [0u8; RECORD_SIZE as usize];
+        for (i, value) in [
+            MAGIC,
+            self.sequence,
+            saved_at as u32,
+            (saved_at >> 32) as u32,
+            states.mean.to_bits(),
+            states.std.to_bits(),
+            0,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            record[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        let crc = crc32_le(0, &record[..CRC_OFFSET]);
+        record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+
+        self.flash.write(address, &record)?;
+        self.next_slot = (self.next_slot + 1) % self.slots;
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(())
+    }
+}
+
+fn word(record: &[u8; RECORD_SIZE as usize], index: usize) -> u32 {
+    u32::from_le_bytes([
+        record[index * 4],
+        record[index * 4 + 1],
+        record[index * 4 + 2],
+        record[index * 4 + 3],
+    ])
+}
+
+fn is_valid(record: &[u8; RECORD_SIZE as usize]) -> bool {
+    word(record, 0) == MAGIC && word(record, 7) == crc32_le(0, &record[..CRC_OFFSET])
+}
+
+/// Find a partition by label, returning its offset and length.
+fn find_partition(flash: &mut FlashStorage<'static>, label: &str) -> Option<(u32, u32)> {
+    let mut merge_buffer = [0u8; SECTOR_SIZE as usize];
+    let mut table_buffer = [0u8; partitions::PARTITION_TABLE_MAX_LEN];
+    let mut storage = RmwNorFlashStorage::new(flash, &mut merge_buffer);
+    let table = match partitions::read_partition_table(&mut storage, &mut table_buffer) {
+        Ok(table) => table,
+        Err(e) => {
+            defmt::info!("Flash: Failed to read partition table: {:?}", e);
+            return None;
+        }
+    };
+    table
+        .iter()
+        .find(|entry| entry.label_as_str() == label)
+        .map(|entry| (entry.offset(), entry.len()))
+}
@@ -1,8 +1,22 @@
+pub mod bme280;
+pub mod bus;
+pub mod co2_compensation;
+pub mod command;
+pub mod gas_index;
+pub mod gas_index_store;
+pub mod modbus;
+pub mod pm_correction;
 pub mod pms5003t;
+pub mod pms_aggregate;
+pub mod pms_channels;
 pub mod s8;
+pub mod scd4x;
+pub mod sen5x;
 pub mod sensor_manager;
 pub mod sgp41;
+pub mod sht4x;
 pub mod task;
 
+pub use command::{SensorCommand, SensorCommands};
 pub use sensor_manager::{SensorData, SensorManager, SharedSensorData};
 pub use task::sensor_task;
@@ -0,0 +1,320 @@
+//! Minimal async Modbus RTU client (master) over `embedded_io_async`.
+//!
+//! Supports reading input/holding registers and writing single/multiple
+//! registers, with CRC16 framing, exception responses and the 3.5 character
+//! inter-frame delay.
+
+use core::fmt::Debug;
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+
+const FUNC_READ_HOLDING: u8 = 0x03;
+const FUNC_READ_INPUT: u8 = 0x04;
+const FUNC_WRITE_SINGLE: u8 = 0x06;
+const FUNC_WRITE_MULTIPLE: u8 = 0x10;
+const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Registers per read/write request allowed by the spec.
+pub const MAX_READ_REGISTERS: usize = 125;
+pub const MAX_WRITE_REGISTERS: usize = 123;
+const MAX_ADU_SIZE: usize = 256;
+const CRC_LEN: usize = 2;
+
+// Bits per character on the wire: start + 8 data + parity/stop + stop.
+const BITS_PER_CHAR: u64 = 11;
+// Above 19200 baud the spec fixes the inter-frame delay.
+const FIXED_INTER_FRAME_DELAY_US: u64 = 1750;
+const FIXED_DELAY_MIN_BAUD: u32 = 19_200;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Exception codes returned by a server in a 5-byte error frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModbusException {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    Other(u8),
+}
+
+impl From<u8> for ModbusException {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => ModbusException::IllegalFunction,
+            0x02 => ModbusException::IllegalDataAddress,
+            0x03 => ModbusException::IllegalDataValue,
+            0x04 => ModbusException::ServerDeviceFailure,
+            0x05 => ModbusException::Acknowledge,
+            0x06 => ModbusException::ServerDeviceBusy,
+            other => ModbusException::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ModbusError {
+    Read,
+    Write,
+    Crc,
+    /// The response did not match the request (address, function, length).
+    InvalidResponse,
+    /// The server answered with an exception frame.
+    Exception(ModbusException),
+    /// Too many registers for a single request.
+    TooManyRegisters,
+    /// No complete response within the timeout.
+    Timeout,
+}
+
+pub struct ModbusRtu<UART> {
+    uart: UART,
+    inter_frame_delay: Duration,
+    last_frame_end: Option<Instant>,
+    timeout: Duration,
+}
+
+impl<UART: embedded_io_async::Read + embedded_io_async::Write> ModbusRtu<UART> {
+    /// `baud_rate` must match the UART configuration; it sets the
+    /// inter-frame delay.
+    pub fn new(uart: UART, baud_rate: u32) -> Self {
+        let inter_frame_delay = if baud_rate > FIXED_DELAY_MIN_BAUD {
+            Duration::from_micros(FIXED_INTER_FRAME_DELAY_US)
+        } else {
+            // 3.5 character times, rounded up.
+            Duration::from_micros((BITS_PER_CHAR * 7 * 1_000_000).div_ceil(2 * baud_rate as u64))
+        };
+        Self {
+            uart,
+            inter_frame_delay,
+            last_frame_end: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set how long a request and its response may take.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Read Input Registers (0x04) starting at `start` into `out`.
+    pub async fn read_input_registers(
+        &mut self,
+        address: u8,
+        start: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        self.read_registers(address, FUNC_READ_INPUT, start, out)
+            .await
+    }
+
+    /// Read Holding Registers (0x03) starting at `start` into `out`.
+    pub async fn read_holding_registers(
+        &mut self,
+        address: u8,
+        start: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        self.read_registers(address, FUNC_READ_HOLDING, start, out)
+            .await
+    }
+
+    /// Write Single Register (0x06).
+    pub async fn write_single_register(
+        &mut self,
+        address: u8,
+        register: u16,
+        value: u16,
+    ) -> Result<(), ModbusError> {
+        let [reg_hi, reg_lo] = register.to_be_bytes();
+        let [val_hi, val_lo] = value.to_be_bytes();
+        let request = [address, FUNC_WRITE_SINGLE, reg_hi, reg_lo, val_hi, val_lo];
+
+        let mut response = [0u8; MAX_ADU_SIZE];
+        let len = self.transact(&request, &mut response).await?;
+
+        // The server echoes the request.
+        if response[..len - CRC_LEN] != request {
+            return Err(ModbusError::InvalidResponse);
+        }
+        Ok(())
+    }
+
+    /// Write Multiple Registers (0x10) starting at `start`.
+    pub async fn write_multiple_registers(
+        &mut self,
+        address: u8,
+        start: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError> {
+        if values.is_empty() || values.len() > MAX_WRITE_REGISTERS {
+            return Err(ModbusError::TooManyRegisters);
+        }
+
+        let mut request = [0u8; MAX_ADU_SIZE];
+        let [start_hi, start_lo] = start.to_be_bytes();
+        let [count_hi, count_lo] = (values.len() as u16).to_be_bytes();
+        request[..7].copy_from_slice(&[
+            address,
+            FUNC_WRITE_MULTIPLE,
+            start_hi,
+            start_lo,
+            count_hi,
+            count_lo,
+            (values.len() * 2) as u8,
+        ]);
+        for (i, value) in values.iter().enumerate() {
+            request[7 + i * 2..9 + i * 2].copy_from_slice(&value.to_be_bytes());
+        }
+        let request_len = 7 + values.len() * 2;
+
+        let mut response = [0u8; MAX_ADU_SIZE];
+        let len = self
+            .transact(&request[..request_len], &mut response)
+            .await?;
+
+        // The server echoes address, function, start and count.
+        if response[..len - CRC_LEN] != request[..6] {
+            return Err(ModbusError::InvalidResponse);
+        }
+        Ok(())
+    }
+
+    async fn read_registers(
+        &mut self,
+        address: u8,
+        function: u8,
+        start: u16,
+        out: &mut [u16],
+    ) -> Result<(), ModbusError> {
+        if out.is_empty() || out.len() > MAX_READ_REGISTERS {
+            return Err(ModbusError::TooManyRegisters);
+        }
+
+        let [start_hi, start_lo] = start.to_be_bytes();
+        let [count_hi, count_lo] = (out.len() as u16).to_be_bytes();
+        let request = [address, function, start_hi, start_lo, count_hi, count_lo];
+
+        let mut response = [0u8; MAX_ADU_SIZE];
+        let len = self.transact(&request, &mut response).await?;
+
+        let byte_count = response[2] as usize;
+        if byte_count != out.len() * 2 || len != 3 + byte_count + CRC_LEN {
+            return Err(ModbusError::InvalidResponse);
+        }
+        for (i, register) in out.iter_mut().enumerate() {
+            *register = u16::from_be_bytes([response[3 + i * 2], response[4 + i * 2]]);
+        }
+        Ok(())
+    }
+
+    /// Send `request` (without CRC) and read the matching response into
+    /// `response`, returning its length including CRC.
+    async fn transact(
+        &mut self,
+        request: &[u8],
+        response: &mut [u8; MAX_ADU_SIZE],
+    ) -> Result<usize, ModbusError> {
+        let address = request[0];
+        let function = request[1];
+
+        let mut frame = [0u8; MAX_ADU_SIZE];
+        frame[..request.len()].copy_from_slice(request);
+        // Modbus CRC is transmitted low byte first.
+        let crc = crc16_modbus(request).to_le_bytes();
+        frame[request.len()..request.len() + CRC_LEN].copy_from_slice(&crc);
+
+        if let Some(last) = self.last_frame_end {
+            Timer::at(last + self.inter_frame_delay).await;
+        }
+
+        let result = with_timeout(
+            self.timeout,
+            self.send_and_receive(&frame[..request.len() + CRC_LEN], response),
+        )
+        .await;
+        self.last_frame_end = Some(Instant::now());
+        let len = result.map_err(|_| ModbusError::Timeout)??;
+
+        let received_crc = u16::from_le_bytes([response[len - 2], response[len - 1]]);
+        if crc16_modbus(&response[..len - CRC_LEN]) != received_crc {
+            return Err(ModbusError::Crc);
+        }
+
+        if response[0] != address {
+            return Err(ModbusError::InvalidResponse);
+        }
+        if response[1] == function | EXCEPTION_FLAG {
+            return Err(ModbusError::Exception(response[2].into()));
+        }
+        if response[1] != function {
+            return Err(ModbusError::InvalidResponse);
+        }
+
+        Ok(len)
+    }
+
+    async fn send_and_receive(
+        &mut self,
+        frame: &[u8],
+        response: &mut [u8; MAX_ADU_SIZE],
+    ) -> Result<usize, ModbusError> {
+        self.uart
+            .write_all(frame)
+            .await
+            .map_err(|_| ModbusError::Write)?;
+        self.uart.flush().await.map_err(|_| ModbusError::Write)?;
+
+        // Address, function and the first byte of the PDU tell us the length.
+        self.read_exact(&mut response[..3]).await?;
+        let function = response[1];
+        let len = if function & EXCEPTION_FLAG != 0 {
+            // Address, function, exception code, CRC.
+            5
+        } else {
+            match function {
+                FUNC_READ_HOLDING | FUNC_READ_INPUT => 3 + response[2] as usize + CRC_LEN,
+                FUNC_WRITE_SINGLE | FUNC_WRITE_MULTIPLE => 6 + CRC_LEN,
+                _ => return Err(ModbusError::InvalidResponse),
+            }
+        };
+        if len > MAX_ADU_SIZE {
+            return Err(ModbusError::InvalidResponse);
+        }
+        self.read_exact(&mut response[3..len]).await?;
+        Ok(len)
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ModbusError> {
+        self.uart
+            .read_exact(buf)
+            .await
+            .map_err(|_| ModbusError::Read)
+    }
+}
+
+impl<UART: BusRecovery> ModbusRtu<UART> {
+    /// Reset the UART, discarding any partial response.
+    pub fn recover(&mut self) {
+        self.uart.recover();
+        self.last_frame_end = Some(Instant::now());
+    }
+}
+
+fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            if (crc & 0x0001) != 0 {
+                crc = (crc >> 1) ^ 0xA001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
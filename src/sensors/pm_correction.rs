@@ -0,0 +1,69 @@
+//! PM2.5 correction algorithms.
+//!
+//! Low-cost optical sensors overestimate PM2.5, especially in humid air. These
+//! corrections map the PMS PM2.5 CF=1 value (µg/m³) and relative humidity
+//! (%) to an estimate closer to a reference monitor.
+
+/// Correction algorithm applied to PM2.5.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pm25Correction {
+    /// AirGradient's PMS5003T correction: the EPA US-wide correction extended
+    /// for high concentrations (smoke), as used by the official firmware.
+    AirGradient,
+    /// EPA 2021 US-wide correction (Barkjohn et al., 2021).
+    Epa2021,
+    /// User-defined `slope * pm25 + intercept`.
+    Linear { slope: f32, intercept: f32 },
+}
+
+impl Pm25Correction {
+    /// Label value for the `algorithm` metric label.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pm25Correction::AirGradient => "airgradient",
+            Pm25Correction::Epa2021 => "epa_2021",
+            Pm25Correction::Linear { .. } => "linear",
+        }
+    }
+
+    /// Corrected PM2.5 in µg/m³, never negative.
+    ///
+    /// Returns `None` if the algorithm needs humidity and none is given.
+    ///
+    /// # Arguments
+    /// * `pm25_cf1` - PM2.5 CF=1 concentration in µg/m³.
+    /// * `humidity` - Relative humidity in % (0-100).
+    pub fn apply(&self, pm25_cf1: f32, humidity: Option<f32>) -> Option<f32> {
+        let rh = humidity.map(|rh| rh.clamp(0.0, 100.0));
+        let corrected = match *self {
+            Pm25Correction::AirGradient => airgradient(pm25_cf1, rh?),
+            Pm25Correction::Epa2021 => epa_2021(pm25_cf1, rh?),
+            Pm25Correction::Linear { slope, intercept } => slope * pm25_cf1 + intercept,
+        };
+        Some(corrected.max(0.0))
+    }
+}
+
+fn epa_2021(x: f32, rh: f32) -> f32 {
+    0.524 * x - 0.0862 * rh + 5.75
+}
+
+/// Piecewise EPA correction, blending into a quadratic fit above 210 µg/m³.
+fn airgradient(x: f32, rh: f32) -> f32 {
+    if x < 30.0 {
+        0.524 * x - 0.0862 * rh + 5.75
+    } else if x < 50.0 {
+        let w = x / 20.0 - 1.5;
+        (0.786 * w + 0.524 * (1.0 - w)) * x - 0.0862 * rh + 5.75
+    } else if x < 210.0 {
+        0.786 * x - 0.0862 * rh + 5.75
+    } else if x < 260.0 {
+        let w = x / 50.0 - 4.2;
+        (0.69 * w + 0.786 * (1.0 - w)) * x - 0.0862 * rh * (1.0 - w)
+            + 2.966 * w
+            + 5.75 * (1.0 - w)
+            + 8.84e-4 * x * x * w
+    } else {
+        2.966 + 0.69 * x + 8.84e-4 * x * x
+    }
+}
@@ -1,94 +1,283 @@
+//! Plantower PMS5003T, PMS7003/PMSA003 and PMSA003I particle sensors.
+//!
+//! They all send the same 32-byte data frame, over UART or, on the PMSA003I,
+//! I2C. Only the meaning of bytes 20..24 of the frame differs by model.
+
 use core::fmt::Debug;
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
+use embedded_io_async::{Error as _, ErrorKind};
+
+use crate::sensors::bus::BusRecovery;
+
+pub const PMSA003I_ADDRESS: u8 = 0x12;
 
 const FRAME_START_1: u8 = 0x42;
 const FRAME_START_2: u8 = 0x4D;
 const EXPECTED_FRAME_LEN: u16 = 28;
-const DATA_PAYLOAD_LEN: usize = 26; // Excludes checksum
-const MAX_SYNC_ATTEMPTS: u32 = 2048;
+const ACK_FRAME_LEN: u16 = 4; // Response to mode/sleep commands
+const HEADER_LEN: usize = 4; // Start bytes + length
+const MAX_FRAME_SIZE: usize = HEADER_LEN + EXPECTED_FRAME_LEN as usize;
+const MAX_READ_BYTES: u32 = 2048; // Give up syncing after this many bytes
+/// Read limit for the PMSA003I, which delivers only a frame a second over
+/// I2C: give up after a few frames rather than MAX_READ_BYTES.
+pub const PMSA003I_MAX_READ_BYTES: u32 = 4 * MAX_FRAME_SIZE as u32;
+const READ_CHUNK_SIZE: usize = 32;
+const FRAMES_PER_CHUNK: usize = READ_CHUNK_SIZE / MAX_FRAME_SIZE + 1;
+// The sensor updates its readings about once a second.
+const PASSIVE_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+// Longer than the ~2.3 s frame interval in stable mode.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Host -> sensor command frames: 0x42 0x4D CMD DATA_H DATA_L LRC_H LRC_L
+const CMD_READ_PASSIVE: u8 = 0xE2;
+const CMD_CHANGE_MODE: u8 = 0xE1;
+const CMD_SLEEP: u8 = 0xE4;
+const CMD_DATA_MODE_PASSIVE: u16 = 0x0000;
+const CMD_DATA_MODE_ACTIVE: u16 = 0x0001;
+const CMD_DATA_SLEEP: u16 = 0x0000;
+const CMD_DATA_WAKEUP: u16 = 0x0001;
+
+/// Sensor model, which decides what bytes 20..24 of a data frame hold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PmsModel {
+    /// Temperature and humidity measured inside the housing.
+    Pms5003t,
+    /// PMS7003, PMSA003 or PMSA003I: particle counts above 5.0 and 10 µm.
+    Pms7003,
+}
+
+/// Reporting mode of the sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PmsMode {
+    /// The sensor streams a frame roughly every second (power-on default).
+    Active,
+    /// The sensor only sends a frame when requested.
+    Passive,
+}
 
 #[derive(Debug, Copy, Clone)]
 pub enum PmsError {
     Read,
+    Write,
     Checksum,
     FrameLen,
-    MaxAttemptsExceeded, // More specific than generic Read error
+    MaxAttemptsExceeded, // No frame start found within the read limit
+    Sleeping,            // Duty cycled off and no reading taken yet
+    Timeout,             // No bytes received, or write stalled, within the I/O timeout
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) struct PmsData {
-    pub(crate) pm1_ae: u16,
-    pub(crate) pm25_ae: u16,
-    pub(crate) pm10_ae: u16,
-    pub(crate) pm03_count: u16,
-    pub(crate) pm05_count: u16,
-    pub(crate) pm10_count: u16,
-    pub(crate) pm25_count: u16,
-    pub(crate) temp: f32,
-    pub(crate) humidity: f32,
+pub struct PmsData {
+    pub pm1_cf1: u16,
+    pub pm25_cf1: u16,
+    pub pm10_cf1: u16,
+    pub pm1_ae: u16,
+    pub pm25_ae: u16,
+    pub pm10_ae: u16,
+    pub pm03_count: u16,
+    pub pm05_count: u16,
+    pub pm10_count: u16,
+    pub pm25_count: u16,
+    /// Particles above 5.0 and 10 µm per 0.1 L (PMS7003 only).
+    pub pm50_count: Option<u16>,
+    pub pm100_count: Option<u16>,
+    /// °C inside the housing (PMS5003T only).
+    pub temp: Option<f32>,
+    /// %RH inside the housing (PMS5003T only).
+    pub humidity: Option<f32>,
+    pub firmware_version: u8,
+    pub error_code: u8,
 }
 
-pub struct Pms5003t<UART> {
-    uart: UART,
+/// Frame decoder counters, cumulative since boot.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PmsStats {
+    /// Valid data frames decoded.
+    pub frames: u32,
+    /// Frames dropped because the checksum did not match.
+    pub checksum_errors: u32,
+    /// Frames dropped because of an unexpected length field.
+    pub length_errors: u32,
+    /// Bytes that were not part of a valid frame.
+    pub bytes_discarded: u32,
 }
 
-impl<UART: embedded_io_async::Read> Pms5003t<UART> {
-    pub fn new(uart: UART) -> Self {
-        Self { uart }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Start1,
+    Start2,
+    Length,
+    Body { frame_len: usize },
+}
+
+/// Incremental PMS frame decoder.
+///
+/// Bytes can be fed in arbitrary chunks; frames split across reads are
+/// reassembled and garbage between frames is skipped. This does no I/O, so it
+/// can be driven from any transport.
+#[derive(Debug, Clone)]
+pub struct PmsDecoder {
+    model: PmsModel,
+    state: DecoderState,
+    buf: [u8; MAX_FRAME_SIZE],
+    pos: usize,
+    stats: PmsStats,
+}
+
+impl Default for PmsDecoder {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub(crate) async fn read(&mut self) -> Result<PmsData, PmsError> {
-        for _ in 0..MAX_SYNC_ATTEMPTS {
-            if let Ok(data) = self.try_read_frame().await {
-                return Ok(data);
-            }
+impl PmsDecoder {
+    /// Create a decoder for PMS5003T frames.
+    pub const fn new() -> Self {
+        Self {
+            model: PmsModel::Pms5003t,
+            state: DecoderState::Start1,
+            buf: [0u8; MAX_FRAME_SIZE],
+            pos: 0,
+            stats: PmsStats {
+                frames: 0,
+                checksum_errors: 0,
+                length_errors: 0,
+                bytes_discarded: 0,
+            },
         }
-        Err(PmsError::MaxAttemptsExceeded)
     }
 
-    async fn try_read_frame(&mut self) -> Result<PmsData, PmsError> {
-        // Sync to frame start
-        if self.read_byte().await? != FRAME_START_1 {
-            return Err(PmsError::Read);
-        }
-        if self.read_byte().await? != FRAME_START_2 {
-            return Err(PmsError::Read);
-        }
+    /// Decode frames of another model.
+    pub const fn with_model(mut self, model: PmsModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn stats(&self) -> PmsStats {
+        self.stats
+    }
+
+    /// Drop any partially received frame, keeping the counters.
+    pub fn reset(&mut self) {
+        self.discard(self.pos);
+        self.state = DecoderState::Start1;
+        self.pos = 0;
+    }
 
-        // Read frame length
-        let mut len_buf = [0u8; 2];
-        self.read_exact(&mut len_buf).await?;
+    /// Feed a chunk of bytes, yielding every data frame completed by it.
+    pub fn feed<'a>(&'a mut self, bytes: &'a [u8]) -> impl Iterator<Item = PmsData> + 'a {
+        bytes.iter().filter_map(move |&byte| self.push(byte))
+    }
 
-        let frame_len = u16::from_be_bytes(len_buf);
-        if frame_len != EXPECTED_FRAME_LEN {
-            return Err(PmsError::FrameLen);
+    /// Feed a single byte, returning a data frame if it completed one.
+    pub fn push(&mut self, byte: u8) -> Option<PmsData> {
+        match self.state {
+            DecoderState::Start1 => {
+                if byte == FRAME_START_1 {
+                    self.buf[0] = byte;
+                    self.pos = 1;
+                    self.state = DecoderState::Start2;
+                } else {
+                    self.discard(1);
+                }
+            }
+            DecoderState::Start2 => {
+                if byte == FRAME_START_2 {
+                    self.buf[1] = byte;
+                    self.pos = 2;
+                    self.state = DecoderState::Length;
+                } else if byte == FRAME_START_1 {
+                    // The previous start byte was noise; this one may be real.
+                    self.discard(1);
+                } else {
+                    self.discard(2);
+                    self.state = DecoderState::Start1;
+                }
+            }
+            DecoderState::Length => {
+                self.buf[self.pos] = byte;
+                self.pos += 1;
+                if self.pos == HEADER_LEN {
+                    let frame_len = u16::from_be_bytes([self.buf[2], self.buf[3]]);
+                    if frame_len == EXPECTED_FRAME_LEN || frame_len == ACK_FRAME_LEN {
+                        self.state = DecoderState::Body {
+                            frame_len: frame_len as usize,
+                        };
+                    } else {
+                        self.stats.length_errors = self.stats.length_errors.wrapping_add(1);
+                        self.resync();
+                    }
+                }
+            }
+            DecoderState::Body { frame_len } => {
+                self.buf[self.pos] = byte;
+                self.pos += 1;
+                if self.pos == HEADER_LEN + frame_len {
+                    self.state = DecoderState::Start1;
+                    return self.finish_frame(frame_len);
+                }
+            }
         }
+        None
+    }
 
-        // Read data payload
-        let mut data_buf = [0u8; 28];
-        self.read_exact(&mut data_buf).await?;
+    fn finish_frame(&mut self, frame_len: usize) -> Option<PmsData> {
+        let total = HEADER_LEN + frame_len;
+        if !Self::verify_checksum(&self.buf[..total]) {
+            self.stats.checksum_errors = self.stats.checksum_errors.wrapping_add(1);
+            self.resync();
+            return None;
+        }
 
-        // Verify checksum
-        if !Self::verify_checksum(&len_buf, &data_buf) {
-            return Err(PmsError::Checksum);
+        if frame_len != EXPECTED_FRAME_LEN as usize {
+            // Command acknowledgement; valid, but carries no data.
+            return None;
         }
 
-        Ok(Self::parse_frame(&data_buf))
+        self.stats.frames = self.stats.frames.wrapping_add(1);
+        let mut data_buf = [0u8; EXPECTED_FRAME_LEN as usize];
+        data_buf.copy_from_slice(&self.buf[HEADER_LEN..total]);
+        Some(Self::parse_frame(&data_buf, self.model))
     }
 
-    fn verify_checksum(len_buf: &[u8; 2], data_buf: &[u8; 28]) -> bool {
-        let mut sum: u16 = FRAME_START_1 as u16 + FRAME_START_2 as u16;
-        sum = sum.wrapping_add(len_buf[0] as u16);
-        sum = sum.wrapping_add(len_buf[1] as u16);
-
-        for &byte in data_buf.iter().take(DATA_PAYLOAD_LEN) {
-            sum = sum.wrapping_add(byte as u16);
+    /// Drop the first byte of a rejected frame and rescan the rest.
+    ///
+    /// If a byte was lost, the rejected frame has swallowed the start of the
+    /// next one, which is then found among the buffered bytes.
+    fn resync(&mut self) {
+        let len = self.pos - 1;
+        let mut rest = [0u8; MAX_FRAME_SIZE];
+        rest[..len].copy_from_slice(&self.buf[1..self.pos]);
+        self.discard(1);
+        self.state = DecoderState::Start1;
+        self.pos = 0;
+        for &byte in &rest[..len] {
+            // Too few bytes to complete a data frame.
+            let _ = self.push(byte);
         }
+    }
 
-        let expected_sum = u16::from_be_bytes([data_buf[26], data_buf[27]]);
+    fn discard(&mut self, count: usize) {
+        self.stats.bytes_discarded = self.stats.bytes_discarded.wrapping_add(count as u32);
+    }
+
+    /// Check the trailing checksum, the sum of all preceding bytes.
+    fn verify_checksum(frame: &[u8]) -> bool {
+        let (body, checksum) = frame.split_at(frame.len() - 2);
+        let sum = body
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+        let expected_sum = u16::from_be_bytes([checksum[0], checksum[1]]);
         sum == expected_sum
     }
 
-    fn parse_frame(data_buf: &[u8; 28]) -> PmsData {
+    fn parse_frame(data_buf: &[u8; 28], model: PmsModel) -> PmsData {
+        // CF=1 "standard particle" concentrations
+        let pm1_cf1 = u16::from_be_bytes([data_buf[0], data_buf[1]]);
+        let pm25_cf1 = u16::from_be_bytes([data_buf[2], data_buf[3]]);
+        let pm10_cf1 = u16::from_be_bytes([data_buf[4], data_buf[5]]);
+        // Atmospheric environment concentrations
         let pm1_ae = u16::from_be_bytes([data_buf[6], data_buf[7]]);
         let pm25_ae = u16::from_be_bytes([data_buf[8], data_buf[9]]);
         let pm10_ae = u16::from_be_bytes([data_buf[10], data_buf[11]]);
@@ -97,10 +286,32 @@ impl<UART: embedded_io_async::Read> Pms5003t<UART> {
         let pm10_count = u16::from_be_bytes([data_buf[16], data_buf[17]]);
         let pm25_count = u16::from_be_bytes([data_buf[18], data_buf[19]]);
 
-        let temp_raw = i16::from_be_bytes([data_buf[20], data_buf[21]]);
-        let hum_raw = u16::from_be_bytes([data_buf[22], data_buf[23]]);
+        let (pm50_count, pm100_count, temp, humidity) = match model {
+            PmsModel::Pms5003t => {
+                let temp_raw = i16::from_be_bytes([data_buf[20], data_buf[21]]);
+                let hum_raw = u16::from_be_bytes([data_buf[22], data_buf[23]]);
+                (
+                    None,
+                    None,
+                    Some((temp_raw as f32) / 10.0),
+                    Some((hum_raw as f32) / 10.0),
+                )
+            }
+            PmsModel::Pms7003 => (
+                Some(u16::from_be_bytes([data_buf[20], data_buf[21]])),
+                Some(u16::from_be_bytes([data_buf[22], data_buf[23]])),
+                None,
+                None,
+            ),
+        };
+
+        let firmware_version = data_buf[24];
+        let error_code = data_buf[25];
 
         PmsData {
+            pm1_cf1,
+            pm25_cf1,
+            pm10_cf1,
             pm1_ae,
             pm25_ae,
             pm10_ae,
@@ -108,21 +319,418 @@ impl<UART: embedded_io_async::Read> Pms5003t<UART> {
             pm05_count,
             pm10_count,
             pm25_count,
-            temp: (temp_raw as f32) / 10.0,
-            humidity: (hum_raw as f32) / 10.0,
+            pm50_count,
+            pm100_count,
+            temp,
+            humidity,
+            firmware_version,
+            error_code,
+        }
+    }
+}
+
+impl PmsData {
+    /// Ambient temperature estimated from the housing temperature, using
+    /// AirGradient's published PMS5003T correction.
+    pub fn compensated_temp(&self) -> Option<f32> {
+        self.temp.map(|temp| {
+            if temp < 10.0 {
+                temp * 1.327 - 6.738
+            } else {
+                temp * 1.181 - 5.113
+            }
+        })
+    }
+
+    /// Ambient relative humidity estimated from the housing humidity, using
+    /// AirGradient's published PMS5003T correction.
+    pub fn compensated_humidity(&self) -> Option<f32> {
+        self.humidity
+            .map(|humidity| (humidity * 1.259 + 7.34).min(100.0))
+    }
+}
+
+pub struct Pms5003t<UART> {
+    uart: UART,
+    mode: PmsMode,
+    decoder: PmsDecoder,
+    timeout: Duration,
+    max_read_bytes: u32,
+}
+
+impl<UART: embedded_io_async::Read + embedded_io_async::Write> Pms5003t<UART> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart,
+            mode: PmsMode::Active,
+            decoder: PmsDecoder::new(),
+            timeout: DEFAULT_TIMEOUT,
+            max_read_bytes: MAX_READ_BYTES,
+        }
+    }
+
+    /// Decode frames of `model` rather than the PMS5003T.
+    pub fn with_model(mut self, model: PmsModel) -> Self {
+        self.decoder = self.decoder.with_model(model);
+        self
+    }
+
+    /// Set how long a single read or command may take before failing with
+    /// `PmsError::Timeout`.
+    ///
+    /// In stable mode the sensor sends a frame only every ~2.3 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many bytes a read may take before giving up on finding a
+    /// valid frame. Defaults to 2048.
+    pub fn with_max_read_bytes(mut self, max_read_bytes: u32) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    /// The reporting mode last requested with `set_mode`.
+    pub fn mode(&self) -> PmsMode {
+        self.mode
+    }
+
+    /// Frame decoder counters.
+    pub fn stats(&self) -> PmsStats {
+        self.decoder.stats()
+    }
+
+    /// Switch between active (streaming) and passive (on request) reporting.
+    pub async fn set_mode(&mut self, mode: PmsMode) -> Result<(), PmsError> {
+        let data = match mode {
+            PmsMode::Active => CMD_DATA_MODE_ACTIVE,
+            PmsMode::Passive => CMD_DATA_MODE_PASSIVE,
+        };
+        self.send_command(CMD_CHANGE_MODE, data).await?;
+        self.mode = mode;
+        Ok(())
+    }
+
+    /// Put the sensor to sleep, stopping the fan and laser.
+    pub async fn sleep(&mut self) -> Result<(), PmsError> {
+        self.send_command(CMD_SLEEP, CMD_DATA_SLEEP).await
+    }
+
+    /// Wake the sensor up from sleep.
+    ///
+    /// The fan needs ~30 seconds to spin up before readings are stable, and the
+    /// sensor comes back in active mode; call `set_mode` again if passive
+    /// reporting is wanted.
+    pub async fn wakeup(&mut self) -> Result<(), PmsError> {
+        self.send_command(CMD_SLEEP, CMD_DATA_WAKEUP).await?;
+        self.mode = PmsMode::Active;
+        Ok(())
+    }
+
+    /// Read the next valid frame.
+    ///
+    /// In passive mode a frame is requested first.
+    pub async fn read(&mut self) -> Result<PmsData, PmsError> {
+        let mut frames: heapless::Vec<PmsData, FRAMES_PER_CHUNK> = heapless::Vec::new();
+        self.read_frames(&mut frames).await?;
+        // The newest frame wins.
+        frames.last().copied().ok_or(PmsError::MaxAttemptsExceeded)
+    }
+
+    /// Read every valid frame received until `deadline`, up to the capacity of
+    /// `frames`.
+    ///
+    /// If no frame arrived by the deadline, waits for one. In passive mode a
+    /// frame is requested every `PASSIVE_REQUEST_INTERVAL`.
+    pub async fn read_until<const N: usize>(
+        &mut self,
+        deadline: Instant,
+        frames: &mut heapless::Vec<PmsData, N>,
+    ) -> Result<(), PmsError> {
+        let collect = async {
+            while !frames.is_full() {
+                self.read_frames(frames).await?;
+                if self.mode == PmsMode::Passive {
+                    Timer::after(PASSIVE_REQUEST_INTERVAL).await;
+                }
+            }
+            Ok(())
+        };
+        match with_deadline(deadline, collect).await {
+            Ok(Err(e)) if frames.is_empty() => return Err(e),
+            _ => {}
         }
+
+        if frames.is_empty() {
+            let data = self.read().await?;
+            let _ = frames.push(data);
+        }
+        Ok(())
+    }
+
+    /// Read until at least one valid frame is decoded, appending every frame
+    /// completed by the bytes read. Frames beyond the capacity of `frames` are
+    /// dropped.
+    async fn read_frames<const N: usize>(
+        &mut self,
+        frames: &mut heapless::Vec<PmsData, N>,
+    ) -> Result<(), PmsError> {
+        if self.mode == PmsMode::Passive {
+            self.send_command(CMD_READ_PASSIVE, 0).await?;
+        }
+
+        let stats_before = self.decoder.stats();
+        let mut buf = [0u8; READ_CHUNK_SIZE];
+        let mut bytes_read: u32 = 0;
+
+        while bytes_read < self.max_read_bytes {
+            let n = with_timeout(self.timeout, self.uart.read(&mut buf))
+                .await
+                .map_err(|_| PmsError::Timeout)?
+                .map_err(|_| PmsError::Read)?;
+            bytes_read += n as u32;
+
+            // Keep feeding the rest of the chunk so a following partial frame
+            // is carried over to the next read.
+            let mut decoded = false;
+            for data in self.decoder.feed(&buf[..n]) {
+                let _ = frames.push(data);
+                decoded = true;
+            }
+            if decoded {
+                return Ok(());
+            }
+        }
+
+        // Report the most specific failure seen while syncing.
+        let stats = self.decoder.stats();
+        if stats.checksum_errors != stats_before.checksum_errors {
+            Err(PmsError::Checksum)
+        } else if stats.length_errors != stats_before.length_errors {
+            Err(PmsError::FrameLen)
+        } else {
+            Err(PmsError::MaxAttemptsExceeded)
+        }
+    }
+
+    async fn send_command(&mut self, cmd: u8, data: u16) -> Result<(), PmsError> {
+        let frame = Self::command_frame(cmd, data);
+        let send = async {
+            self.uart.write_all(&frame).await?;
+            self.uart.flush().await
+        };
+        with_timeout(self.timeout, send)
+            .await
+            .map_err(|_| PmsError::Timeout)?
+            .map_err(|_| PmsError::Write)
+    }
+
+    fn command_frame(cmd: u8, data: u16) -> [u8; 7] {
+        let [data_h, data_l] = data.to_be_bytes();
+        let mut frame = [FRAME_START_1, FRAME_START_2, cmd, data_h, data_l, 0, 0];
+
+        let sum = frame[0..5]
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        let [sum_h, sum_l] = sum.to_be_bytes();
+        frame[5] = sum_h;
+        frame[6] = sum_l;
+        frame
     }
+}
+
+impl<UART: BusRecovery> Pms5003t<UART> {
+    /// Reset the UART and drop any partial frame.
+    pub fn recover(&mut self) {
+        self.uart.recover();
+        self.decoder.reset();
+    }
+}
+
+/// The PMSA003I refreshes its frame about once a second.
+const PMSA003I_FRAME_INTERVAL: Duration = Duration::from_secs(1);
 
-    async fn read_byte(&mut self) -> Result<u8, PmsError> {
-        let mut buf = [0u8; 1];
-        self.uart
-            .read_exact(&mut buf)
+/// PMSA003I frames read over I2C, presented as the byte stream the UART
+/// models send.
+///
+/// Each read fetches a whole frame, so `buf` should hold at least 32 bytes,
+/// and waits until the sensor has refreshed it. The PMSA003I takes no
+/// commands over I2C, so writes fail with `ErrorKind::Unsupported`.
+pub struct PmsI2c<I2C> {
+    i2c: I2C,
+    last_read: Option<Instant>,
+}
+
+impl<I2C> PmsI2c<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            last_read: None,
+        }
+    }
+}
+
+impl<I2C> embedded_io_async::ErrorType for PmsI2c<I2C> {
+    type Error = ErrorKind;
+}
+
+impl<I2C: embedded_hal_async::i2c::I2c> embedded_io_async::Read for PmsI2c<I2C> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(last_read) = self.last_read {
+            Timer::at(last_read + PMSA003I_FRAME_INTERVAL).await;
+        }
+        let len = buf.len().min(MAX_FRAME_SIZE);
+        self.i2c
+            .read(PMSA003I_ADDRESS, &mut buf[..len])
             .await
-            .map_err(|_| PmsError::Read)?;
-        Ok(buf[0])
+            .map_err(|_| ErrorKind::Other)?;
+        self.last_read = Some(Instant::now());
+        Ok(len)
+    }
+}
+
+impl<I2C> embedded_io_async::Write for PmsI2c<I2C> {
+    async fn write(&mut self, _buf: &[u8]) -> Result<usize, Self::Error> {
+        Err(ErrorKind::Unsupported)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<I2C: BusRecovery> BusRecovery for PmsI2c<I2C> {
+    fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
+
+/// Transport of a PMS sensor chosen at runtime: a UART, or I2C for the
+/// PMSA003I.
+pub enum PmsLink<UART, I2C> {
+    Uart(UART),
+    I2c(PmsI2c<I2C>),
+}
+
+impl<UART, I2C> embedded_io_async::ErrorType for PmsLink<UART, I2C> {
+    type Error = ErrorKind;
+}
+
+impl<UART, I2C> embedded_io_async::Read for PmsLink<UART, I2C>
+where
+    UART: embedded_io_async::Read,
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self {
+            Self::Uart(uart) => uart.read(buf).await.map_err(|e| e.kind()),
+            Self::I2c(i2c) => i2c.read(buf).await,
+        }
+    }
+}
+
+impl<UART: embedded_io_async::Write, I2C> embedded_io_async::Write for PmsLink<UART, I2C> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        match self {
+            Self::Uart(uart) => uart.write(buf).await.map_err(|e| e.kind()),
+            Self::I2c(i2c) => i2c.write(buf).await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Self::Uart(uart) => uart.flush().await.map_err(|e| e.kind()),
+            Self::I2c(i2c) => i2c.flush().await,
+        }
+    }
+}
+
+impl<UART: BusRecovery, I2C: BusRecovery> BusRecovery for PmsLink<UART, I2C> {
+    fn recover(&mut self) {
+        match self {
+            Self::Uart(uart) => uart.recover(),
+            Self::I2c(i2c) => i2c.recover(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A data frame with the given PM2.5 CF=1 value and a valid checksum.
+    fn frame(pm25_cf1: u16) -> [u8; MAX_FRAME_SIZE] {
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        frame[..4].copy_from_slice(&[FRAME_START_1, FRAME_START_2, 0, EXPECTED_FRAME_LEN as u8]);
+        frame[6..8].copy_from_slice(&pm25_cf1.to_be_bytes());
+        let sum = frame[..30]
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        frame[30..].copy_from_slice(&sum.to_be_bytes());
+        frame
+    }
+
+    fn decode(decoder: &mut PmsDecoder, bytes: &[u8]) -> heapless::Vec<u16, 4> {
+        decoder.feed(bytes).map(|data| data.pm25_cf1).collect()
+    }
+
+    #[test]
+    fn frame_split_across_chunks() {
+        let mut decoder = PmsDecoder::new();
+        let frame = frame(12);
+        assert!(decode(&mut decoder, &frame[..3]).is_empty());
+        assert!(decode(&mut decoder, &frame[3..20]).is_empty());
+        assert_eq!(decode(&mut decoder, &frame[20..]), [12]);
+        assert_eq!(decoder.stats().frames, 1);
+        assert_eq!(decoder.stats().bytes_discarded, 0);
+    }
+
+    #[test]
+    fn garbage_between_frames() {
+        let mut decoder = PmsDecoder::new();
+        assert_eq!(decode(&mut decoder, &[0x00, FRAME_START_1, 0x13]), []);
+        assert_eq!(decode(&mut decoder, &frame(1)), [1]);
+        assert_eq!(decode(&mut decoder, &[FRAME_START_1, FRAME_START_1]), []);
+        assert_eq!(decode(&mut decoder, &frame(2)), [2]);
+        assert_eq!(decoder.stats().bytes_discarded, 5);
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut decoder = PmsDecoder::new();
+        let mut bad = frame(1);
+        bad[10] ^= 0xFF;
+        assert_eq!(decode(&mut decoder, &bad), []);
+        assert_eq!(decode(&mut decoder, &frame(2)), [2]);
+        assert_eq!(decoder.stats().checksum_errors, 1);
+        assert_eq!(decoder.stats().bytes_discarded, MAX_FRAME_SIZE as u32);
+    }
+
+    #[test]
+    fn bad_length() {
+        let mut decoder = PmsDecoder::new();
+        assert_eq!(
+            decode(&mut decoder, &[FRAME_START_1, FRAME_START_2, 0, 99]),
+            []
+        );
+        assert_eq!(decode(&mut decoder, &frame(3)), [3]);
+        assert_eq!(decoder.stats().length_errors, 1);
+        assert_eq!(decoder.stats().bytes_discarded, HEADER_LEN as u32);
     }
 
-    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), PmsError> {
-        self.uart.read_exact(buf).await.map_err(|_| PmsError::Read)
+    #[test]
+    fn dropped_byte_loses_only_that_frame() {
+        let mut decoder = PmsDecoder::new();
+        let mut bytes: heapless::Vec<u8, 64> = heapless::Vec::new();
+        bytes.extend_from_slice(&frame(1)[..20]).unwrap();
+        bytes.extend_from_slice(&frame(1)[21..]).unwrap();
+        bytes.extend_from_slice(&frame(2)).unwrap();
+        assert_eq!(decode(&mut decoder, &bytes), [2]);
+        assert_eq!(decoder.stats().checksum_errors, 1);
+        assert_eq!(decoder.stats().bytes_discarded, 31);
     }
 }
@@ -0,0 +1,82 @@
+//! Aggregating several PMS frames from one poll into a single reading.
+
+use crate::sensors::pms5003t::PmsData;
+
+/// Maximum number of frames aggregated per poll.
+pub const MAX_FRAMES: usize = 16;
+
+/// How frames received during a poll are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmsAggregation {
+    /// Per-field median.
+    Median,
+    /// Per-field mean after dropping the lowest and highest quarter.
+    TrimmedMean,
+}
+
+/// Combine frames field by field. Returns `None` if `frames` is empty.
+///
+/// Firmware version and error code are taken from the newest frame; fields
+/// the model doesn't report stay `None`.
+pub fn aggregate(frames: &[PmsData], method: PmsAggregation) -> Option<PmsData> {
+    let latest = frames.last()?;
+    let field = |get: fn(&PmsData) -> Option<f32>| aggregate_field(frames, get, method);
+    // Values are non-negative, so adding 0.5 and truncating rounds.
+    let round = |value: f32| (value + 0.5) as u16;
+    let count = |get: fn(&PmsData) -> u16| {
+        aggregate_field(frames, |d| Some(get(d) as f32), method).map_or(0, round)
+    };
+    let optional_count = |get: fn(&PmsData) -> Option<u16>| {
+        aggregate_field(frames, |d| get(d).map(f32::from), method).map(round)
+    };
+
+    Some(PmsData {
+        pm1_cf1: count(|d| d.pm1_cf1),
+        pm25_cf1: count(|d| d.pm25_cf1),
+        pm10_cf1: count(|d| d.pm10_cf1),
+        pm1_ae: count(|d| d.pm1_ae),
+        pm25_ae: count(|d| d.pm25_ae),
+        pm10_ae: count(|d| d.pm10_ae),
+        pm03_count: count(|d| d.pm03_count),
+        pm05_count: count(|d| d.pm05_count),
+        pm10_count: count(|d| d.pm10_count),
+        pm25_count: count(|d| d.pm25_count),
+        pm50_count: optional_count(|d| d.pm50_count),
+        pm100_count: optional_count(|d| d.pm100_count),
+        temp: field(|d| d.temp),
+        humidity: field(|d| d.humidity),
+        firmware_version: latest.firmware_version,
+        error_code: latest.error_code,
+    })
+}
+
+/// Combine the values `get` returns, or `None` if it returns none.
+fn aggregate_field(
+    frames: &[PmsData],
+    get: impl Fn(&PmsData) -> Option<f32>,
+    method: PmsAggregation,
+) -> Option<f32> {
+    let mut values: heapless::Vec<f32, MAX_FRAMES> =
+        frames.iter().filter_map(get).take(MAX_FRAMES).collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(f32::total_cmp);
+    let n = values.len();
+
+    let value = match method {
+        PmsAggregation::Median => {
+            if n % 2 == 1 {
+                values[n / 2]
+            } else {
+                (values[n / 2 - 1] + values[n / 2]) / 2.0
+            }
+        }
+        PmsAggregation::TrimmedMean => {
+            let trim = n / 4;
+            let kept = &values[trim..n - trim];
+            kept.iter().sum::<f32>() / kept.len() as f32
+        }
+    };
+    Some(value)
+}
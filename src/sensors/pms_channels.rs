@@ -0,0 +1,95 @@
+//! Combining readings from two PMS sensors (O-1PPT).
+//!
+//! The combined value follows the PurpleAir A/B channel approach: if both
+//! channels report and agree, they are averaged; if they disagree, there is
+//! no combined value; if only one reports, it is used alone.
+
+use crate::sensors::pms5003t::{PmsData, PmsStats};
+
+/// Channels agree if their PM2.5 CF=1 values are within this many µg/m³...
+const MAX_ABS_DIFF_UGM3: f32 = 5.0;
+/// ...or within this fraction of their mean.
+const MAX_REL_DIFF: f32 = 0.7;
+
+/// State of one PMS channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PmsChannel {
+    /// Latest reading, if any.
+    pub data: Option<PmsData>,
+    /// Number of frames combined into `data` on the last poll.
+    pub frame_count: usize,
+    /// Frame decoder counters.
+    pub stats: PmsStats,
+}
+
+/// How closely the two channels agree on PM2.5 (CF=1).
+#[derive(Debug, Clone, Copy)]
+pub struct PmsAgreement {
+    /// Absolute difference in µg/m³.
+    pub abs_diff: f32,
+    /// Difference relative to the mean of both channels.
+    pub rel_diff: f32,
+    /// Whether the difference is within the PurpleAir-style confidence bounds.
+    pub agree: bool,
+}
+
+impl PmsAgreement {
+    pub fn between(a: &PmsData, b: &PmsData) -> Self {
+        let a = a.pm25_cf1 as f32;
+        let b = b.pm25_cf1 as f32;
+        let abs_diff = if a > b { a - b } else { b - a };
+        let mean = (a + b) / 2.0;
+        let rel_diff = if mean > 0.0 { abs_diff / mean } else { 0.0 };
+
+        Self {
+            abs_diff,
+            rel_diff,
+            agree: abs_diff <= MAX_ABS_DIFF_UGM3 || rel_diff <= MAX_REL_DIFF,
+        }
+    }
+}
+
+/// Combine the channel readings into one value.
+///
+/// Returns `None` if neither channel has a reading, or if both do but
+/// disagree: averaging would still carry half of a faulty channel's error.
+pub fn combine(a: Option<&PmsData>, b: Option<&PmsData>) -> Option<PmsData> {
+    match (a, b) {
+        (Some(a), Some(b)) if PmsAgreement::between(a, b).agree => Some(average(a, b)),
+        (Some(_), Some(_)) => None,
+        (Some(x), None) | (None, Some(x)) => Some(*x),
+        (None, None) => None,
+    }
+}
+
+fn average(a: &PmsData, b: &PmsData) -> PmsData {
+    let avg = |x: u16, y: u16| ((x as u32 + y as u32).div_ceil(2)) as u16;
+    let avg_count = |x: Option<u16>, y: Option<u16>| match (x, y) {
+        (Some(x), Some(y)) => Some(avg(x, y)),
+        _ => x.or(y),
+    };
+    let avg_field = |x: Option<f32>, y: Option<f32>| match (x, y) {
+        (Some(x), Some(y)) => Some((x + y) / 2.0),
+        _ => x.or(y),
+    };
+
+    PmsData {
+        pm1_cf1: avg(a.pm1_cf1, b.pm1_cf1),
+        pm25_cf1: avg(a.pm25_cf1, b.pm25_cf1),
+        pm10_cf1: avg(a.pm10_cf1, b.pm10_cf1),
+        pm1_ae: avg(a.pm1_ae, b.pm1_ae),
+        pm25_ae: avg(a.pm25_ae, b.pm25_ae),
+        pm10_ae: avg(a.pm10_ae, b.pm10_ae),
+        pm03_count: avg(a.pm03_count, b.pm03_count),
+        pm05_count: avg(a.pm05_count, b.pm05_count),
+        pm10_count: avg(a.pm10_count, b.pm10_count),
+        pm25_count: avg(a.pm25_count, b.pm25_count),
+        pm50_count: avg_count(a.pm50_count, b.pm50_count),
+        pm100_count: avg_count(a.pm100_count, b.pm100_count),
+        temp: avg_field(a.temp, b.temp),
+        humidity: avg_field(a.humidity, b.humidity),
+        // Not meaningful to combine; report channel A's.
+        firmware_version: a.firmware_version,
+        error_code: a.error_code,
+    }
+}
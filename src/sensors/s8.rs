@@ -1,4 +1,8 @@
 use core::fmt::Debug;
+use embassy_time::{Duration, Timer};
+
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::modbus::{ModbusError, ModbusException, ModbusRtu};
 
 #[derive(Debug, Copy, Clone)]
 pub enum S8Error {
@@ -6,96 +10,227 @@ pub enum S8Error {
     WriteError,
     ChecksumError,
     InvalidHeader,
+    Exception(ModbusException),
+    CalibrationNotAcknowledged,
+    Timeout,
+}
+
+impl From<ModbusError> for S8Error {
+    fn from(e: ModbusError) -> Self {
+        match e {
+            ModbusError::Read => S8Error::ReadError,
+            ModbusError::Write => S8Error::WriteError,
+            ModbusError::Crc => S8Error::ChecksumError,
+            ModbusError::InvalidResponse | ModbusError::TooManyRegisters => S8Error::InvalidHeader,
+            ModbusError::Exception(code) => S8Error::Exception(code),
+            ModbusError::Timeout => S8Error::Timeout,
+        }
+    }
+}
+
+/// A fault flag from IR1 (meter status).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum S8Fault {
+    FatalError,
+    OffsetRegulationError,
+    AlgorithmError,
+    OutputError,
+    SelfDiagnosticsError,
+    OutOfRange,
+    MemoryError,
+}
+
+impl S8Fault {
+    const ALL: [S8Fault; 7] = [
+        S8Fault::FatalError,
+        S8Fault::OffsetRegulationError,
+        S8Fault::AlgorithmError,
+        S8Fault::OutputError,
+        S8Fault::SelfDiagnosticsError,
+        S8Fault::OutOfRange,
+        S8Fault::MemoryError,
+    ];
+
+    const fn bit(self) -> u16 {
+        match self {
+            S8Fault::FatalError => 1 << 0,
+            S8Fault::OffsetRegulationError => 1 << 1,
+            S8Fault::AlgorithmError => 1 << 2,
+            S8Fault::OutputError => 1 << 3,
+            S8Fault::SelfDiagnosticsError => 1 << 4,
+            S8Fault::OutOfRange => 1 << 5,
+            S8Fault::MemoryError => 1 << 6,
+        }
+    }
+}
+
+/// Decoded IR1 (meter status) register.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct S8Status(u16);
+
+impl S8Status {
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(&self, fault: S8Fault) -> bool {
+        self.0 & fault.bit() != 0
+    }
+
+    /// True if no fault flag is set.
+    pub fn is_ok(&self) -> bool {
+        self.faults().next().is_none()
+    }
+
+    /// The fault flags that are set. Reserved bits are ignored.
+    pub fn faults(&self) -> impl Iterator<Item = S8Fault> + '_ {
+        S8Fault::ALL
+            .into_iter()
+            .filter(move |fault| self.contains(*fault))
+    }
+}
+
+/// One poll of the sensor: CO2 and the meter status read alongside it.
+#[derive(Debug, Copy, Clone)]
+pub struct S8Reading {
+    pub co2: u16,
+    pub status: S8Status,
+}
+
+/// Identification registers, read once at init.
+#[derive(Debug, Copy, Clone)]
+pub struct S8Info {
+    /// Sensor type ID (24 bits).
+    pub type_id: u32,
+    /// Firmware version as (main, sub).
+    pub firmware_version: (u8, u8),
+    pub sensor_id: u32,
 }
 
 pub struct S8<UART> {
-    uart: UART,
+    modbus: ModbusRtu<UART>,
 }
 
 // Modbus protocol constants
+const BAUD_RATE: u32 = 9600;
+// S8 uses 0xFE as "Any Address".
 const MODBUS_ADDR_ANY: u8 = 0xFE;
-const MODBUS_FUNC_READ_INPUT: u8 = 0x04;
-const MODBUS_IR4_CO2_HIGH: u8 = 0x00;
-const MODBUS_IR4_CO2_LOW: u8 = 0x03;
-const MODBUS_READ_LEN_HIGH: u8 = 0x00;
-const MODBUS_READ_LEN_LOW: u8 = 0x01;
-const RESPONSE_BYTE_COUNT: u8 = 0x02;
+// IR1 (Input Register 1) is the meter status, at address 0x0000 (0-indexed).
+// IR2 and IR3 (alarm and output status) sit between it and IR4, CO2, so one
+// request covers IR1..IR4.
+const IR1_METER_STATUS: u16 = 0x0000;
+const IR1_TO_IR4_COUNT: usize = 4;
+// IR26..IR31: type ID high/low, memory map version, firmware version,
+// sensor ID high/low.
+const IR26_TYPE_ID_HIGH: u16 = 0x0019;
+const IR26_TO_IR31_COUNT: usize = 6;
+// HR1 is the acknowledgement register, HR2 the special command register.
+const HR1_ACK: u16 = 0x0000;
+const HR2_COMMAND: u16 = 0x0001;
+const CMD_BACKGROUND_CALIBRATION: u16 = 0x7C06;
+const ACK_BACKGROUND_CALIBRATION: u16 = 1 << 5;
+// The sensor needs at least one measurement period (2 s) to calibrate.
+const CALIBRATION_DELAY: Duration = Duration::from_secs(2);
+const CALIBRATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const CALIBRATION_POLLS: u32 = 5;
+// HR32 (Holding Register 32) is the ABC period in hours; 0 disables ABC.
+const HR32_ABC_PERIOD: u16 = 0x001F;
 
 impl<UART: embedded_io_async::Read + embedded_io_async::Write> S8<UART> {
+    /// `uart` must be configured for 9600 baud, 8N1.
     pub fn new(uart: UART) -> Self {
-        Self { uart }
+        Self {
+            modbus: ModbusRtu::new(uart, BAUD_RATE),
+        }
     }
 
-    pub(crate) async fn get_co2(&mut self) -> Result<u16, S8Error> {
-        // Modbus command: Addr(0xFE), Func(0x04), Reg(0x0003), Len(0x0001), CRC
-        // S8 uses 0xFE as "Any Address". IR4 (Input Register 4) is CO2.
-        // IR4 is address 0x0003 (0-indexed).
-        let mut cmd = [
-            MODBUS_ADDR_ANY,
-            MODBUS_FUNC_READ_INPUT,
-            MODBUS_IR4_CO2_HIGH,
-            MODBUS_IR4_CO2_LOW,
-            MODBUS_READ_LEN_HIGH,
-            MODBUS_READ_LEN_LOW,
-            0x00, // CRC low byte (calculated below)
-            0x00, // CRC high byte (calculated below)
-        ];
-
-        // Calculate and append CRC16 Modbus
-        let crc = crc16_modbus(&cmd[0..6]);
-        cmd[6] = (crc & 0xFF) as u8; // CRC low byte
-        cmd[7] = ((crc >> 8) & 0xFF) as u8; // CRC high byte
-
-        self.uart
-            .write_all(&cmd)
-            .await
-            .map_err(|_| S8Error::WriteError)?;
-
-        let mut buf = [0u8; 7];
-        self.uart
-            .read_exact(&mut buf)
-            .await
-            .map_err(|_| S8Error::ReadError)?;
-
-        validate_response(&buf)?;
-
-        let co2 = ((buf[3] as u16) << 8) | (buf[4] as u16);
-        Ok(co2)
+    /// Set how long a Modbus transaction may take before failing with
+    /// `S8Error::Timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.modbus = self.modbus.with_timeout(timeout);
+        self
     }
-}
 
-fn validate_response(buf: &[u8; 7]) -> Result<(), S8Error> {
-    if buf[0] != MODBUS_ADDR_ANY
-        || buf[1] != MODBUS_FUNC_READ_INPUT
-        || buf[2] != RESPONSE_BYTE_COUNT
-    {
-        return Err(S8Error::InvalidHeader);
+    /// Read CO2 (ppm) together with the meter status flags.
+    pub(crate) async fn read(&mut self) -> Result<S8Reading, S8Error> {
+        let mut registers = [0u16; IR1_TO_IR4_COUNT];
+        self.modbus
+            .read_input_registers(MODBUS_ADDR_ANY, IR1_METER_STATUS, &mut registers)
+            .await?;
+        Ok(S8Reading {
+            co2: registers[3],
+            status: S8Status::from_bits(registers[0]),
+        })
     }
 
-    // Modbus CRC is transmitted as [CRC_LOW, CRC_HIGH]
-    // buf[5] = CRC_LOW, buf[6] = CRC_HIGH
-    // Reconstruct as: (HIGH << 8) | LOW
-    let received_crc = ((buf[6] as u16) << 8) | (buf[5] as u16);
-    let calculated_crc = crc16_modbus(&buf[0..5]);
+    /// Read the sensor type ID, firmware version and sensor ID.
+    pub async fn get_info(&mut self) -> Result<S8Info, S8Error> {
+        let mut registers = [0u16; IR26_TO_IR31_COUNT];
+        self.modbus
+            .read_input_registers(MODBUS_ADDR_ANY, IR26_TYPE_ID_HIGH, &mut registers)
+            .await?;
+        let [fw_main, fw_sub] = registers[3].to_be_bytes();
+        Ok(S8Info {
+            type_id: ((registers[0] as u32 & 0xFF) << 16) | registers[1] as u32,
+            firmware_version: (fw_main, fw_sub),
+            sensor_id: ((registers[4] as u32) << 16) | registers[5] as u32,
+        })
+    }
 
-    if calculated_crc != received_crc {
-        return Err(S8Error::ChecksumError);
+    /// Read the automatic baseline correction period in hours (0 = disabled).
+    pub async fn get_abc_period(&mut self) -> Result<u16, S8Error> {
+        let mut hours = [0u16; 1];
+        self.modbus
+            .read_holding_registers(MODBUS_ADDR_ANY, HR32_ABC_PERIOD, &mut hours)
+            .await?;
+        Ok(hours[0])
     }
 
-    Ok(())
-}
+    /// Set the automatic baseline correction period in hours; 0 disables ABC.
+    ///
+    /// This is stored in the sensor's EEPROM, so avoid writing it needlessly.
+    pub async fn set_abc_period(&mut self, hours: u16) -> Result<(), S8Error> {
+        self.modbus
+            .write_single_register(MODBUS_ADDR_ANY, HR32_ABC_PERIOD, hours)
+            .await?;
+        Ok(())
+    }
 
-fn crc16_modbus(data: &[u8]) -> u16 {
-    let mut crc: u16 = 0xFFFF;
-    for &byte in data {
-        crc ^= byte as u16;
-        for _ in 0..8 {
-            if (crc & 0x0001) != 0 {
-                crc = (crc >> 1) ^ 0xA001;
-            } else {
-                crc >>= 1;
+    /// Run a background calibration, setting the current reading to 400 ppm.
+    ///
+    /// The sensor must have been in fresh (outdoor) air for several minutes.
+    /// Follows the documented sequence: clear the acknowledgement register,
+    /// write the calibration command, then poll for the acknowledgement bit.
+    pub async fn calibrate_background(&mut self) -> Result<(), S8Error> {
+        self.modbus
+            .write_single_register(MODBUS_ADDR_ANY, HR1_ACK, 0)
+            .await?;
+        self.modbus
+            .write_single_register(MODBUS_ADDR_ANY, HR2_COMMAND, CMD_BACKGROUND_CALIBRATION)
+            .await?;
+
+        Timer::after(CALIBRATION_DELAY).await;
+        for _ in 0..CALIBRATION_POLLS {
+            let mut ack = [0u16; 1];
+            self.modbus
+                .read_holding_registers(MODBUS_ADDR_ANY, HR1_ACK, &mut ack)
+                .await?;
+            if ack[0] & ACK_BACKGROUND_CALIBRATION != 0 {
+                return Ok(());
             }
+            Timer::after(CALIBRATION_POLL_INTERVAL).await;
         }
+        Err(S8Error::CalibrationNotAcknowledged)
+    }
+}
+
+impl<UART: BusRecovery> S8<UART> {
+    /// Reset the UART after repeated failures.
+    pub fn recover(&mut self) {
+        self.modbus.recover();
     }
-    crc
 }
@@ -0,0 +1,307 @@
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::sgp41::crc8;
+
+const SCD4X_ADDRESS: u8 = 0x62;
+
+// Commands
+const CMD_START_PERIODIC_MEASUREMENT: u16 = 0x21B1;
+const CMD_START_LOW_POWER_PERIODIC_MEASUREMENT: u16 = 0x21AC;
+const CMD_STOP_PERIODIC_MEASUREMENT: u16 = 0x3F86;
+const CMD_READ_MEASUREMENT: u16 = 0xEC05;
+const CMD_GET_DATA_READY_STATUS: u16 = 0xE4B8;
+const CMD_MEASURE_SINGLE_SHOT: u16 = 0x219D;
+const CMD_SET_SENSOR_ALTITUDE: u16 = 0x2427;
+const CMD_GET_SENSOR_ALTITUDE: u16 = 0x2322;
+const CMD_SET_AMBIENT_PRESSURE: u16 = 0xE000;
+const CMD_PERFORM_FORCED_RECALIBRATION: u16 = 0x362F;
+const CMD_SET_ASC_ENABLED: u16 = 0x2416;
+const CMD_GET_ASC_ENABLED: u16 = 0x2313;
+const CMD_GET_SERIAL_NUMBER: u16 = 0x3682;
+const CMD_REINIT: u16 = 0x3646;
+
+// Timing, from the datasheet
+const COMMAND_DELAY_MS: u64 = 1;
+const STOP_PERIODIC_DELAY_MS: u64 = 500;
+const SINGLE_SHOT_DELAY_MS: u64 = 5000;
+const FORCED_RECALIBRATION_DELAY_MS: u64 = 400;
+const REINIT_DELAY_MS: u64 = 30;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+// The low 11 bits of the data-ready status are 0 while no data is ready.
+const DATA_READY_MASK: u16 = 0x07FF;
+// Forced recalibration response on failure.
+const FRC_FAILED: u16 = 0xFFFF;
+const FRC_CORRECTION_OFFSET: i32 = 0x8000;
+
+const WORD_SIZE: usize = 3; // 2 bytes data + 1 CRC
+const MAX_WORDS: usize = 3;
+
+/// One reading.
+#[derive(Debug, Copy, Clone)]
+pub struct Scd4xMeasurement {
+    /// ppm.
+    pub co2: u16,
+    /// °C, measured inside the sensor.
+    pub temperature: f32,
+    /// %RH, measured inside the sensor.
+    pub humidity: f32,
+}
+
+/// How the sensor takes measurements.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scd4xMode {
+    /// Every 5 s.
+    Periodic,
+    /// Every 30 s.
+    LowPowerPeriodic,
+    /// One measurement per `start_single_shot`; SCD41 only.
+    SingleShot,
+}
+
+impl Scd4xMode {
+    /// Time from starting the mode until a measurement is ready.
+    pub const fn measurement_time(self) -> Duration {
+        match self {
+            Scd4xMode::Periodic | Scd4xMode::SingleShot => Duration::from_secs(5),
+            Scd4xMode::LowPowerPeriodic => Duration::from_secs(30),
+        }
+    }
+}
+
+pub struct Scd4x<I2C> {
+    i2c: I2C,
+    address: u8,
+    timeout: Duration,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Scd4xError {
+    I2cError,
+    CrcError,
+    /// No measurement has completed since the last read.
+    NotReady,
+    /// The sensor rejected the forced recalibration, e.g. because it had not
+    /// been measuring for long enough beforehand.
+    RecalibrationFailed,
+    Timeout,
+}
+
+impl<I2C> Scd4x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Create a new SCD4x driver instance
+    ///
+    /// Note: Most settings can only be changed while the sensor is idle, so
+    /// call `stop_periodic_measurement` first; the sensor keeps measuring
+    /// through a reset of the host.
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            address: SCD4X_ADDRESS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set how long a single I2C transfer may take before failing with
+    /// `Scd4xError::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Start measuring in `mode`
+    pub async fn start(&mut self, mode: Scd4xMode) -> Result<(), Scd4xError> {
+        match mode {
+            Scd4xMode::Periodic => self.command(CMD_START_PERIODIC_MEASUREMENT, &[], 0).await,
+            Scd4xMode::LowPowerPeriodic => {
+                self.command(CMD_START_LOW_POWER_PERIODIC_MEASUREMENT, &[], 0)
+                    .await
+            }
+            Scd4xMode::SingleShot => self.start_single_shot().await,
+        }
+    }
+
+    /// Stop periodic measurement and wait until the sensor is idle
+    pub async fn stop_periodic_measurement(&mut self) -> Result<(), Scd4xError> {
+        self.command(CMD_STOP_PERIODIC_MEASUREMENT, &[], STOP_PERIODIC_DELAY_MS)
+            .await
+    }
+
+    /// Trigger a single measurement, ready after 5 s (SCD41 only)
+    pub async fn start_single_shot(&mut self) -> Result<(), Scd4xError> {
+        self.command(CMD_MEASURE_SINGLE_SHOT, &[], 0).await
+    }
+
+    /// Trigger a single measurement and wait for it (SCD41 only)
+    pub async fn measure_single_shot(&mut self) -> Result<Scd4xMeasurement, Scd4xError> {
+        self.start_single_shot().await?;
+        Timer::after_millis(SINGLE_SHOT_DELAY_MS).await;
+        self.read_measurement().await
+    }
+
+    /// Check whether a measurement has completed since the last read
+    pub async fn data_ready(&mut self) -> Result<bool, Scd4xError> {
+        let [status] = self.read_words(CMD_GET_DATA_READY_STATUS).await?;
+        Ok(status & DATA_READY_MASK != 0)
+    }
+
+    /// Read the latest measurement
+    ///
+    /// Returns `Scd4xError::NotReady` if `data_ready` is false.
+    pub async fn read_measurement(&mut self) -> Result<Scd4xMeasurement, Scd4xError> {
+        if !self.data_ready().await? {
+            return Err(Scd4xError::NotReady);
+        }
+        let [co2, t_ticks, rh_ticks] = self.read_words(CMD_READ_MEASUREMENT).await?;
+        Ok(Scd4xMeasurement {
+            co2,
+            temperature: Self::ticks_to_temperature(t_ticks),
+            humidity: Self::ticks_to_humidity(rh_ticks),
+        })
+    }
+
+    /// Set the altitude used for pressure compensation, in meters above sea
+    /// level (idle only)
+    pub async fn set_sensor_altitude(&mut self, meters: u16) -> Result<(), Scd4xError> {
+        self.command(CMD_SET_SENSOR_ALTITUDE, &[meters], COMMAND_DELAY_MS)
+            .await
+    }
+
+    pub async fn get_sensor_altitude(&mut self) -> Result<u16, Scd4xError> {
+        let [meters] = self.read_words(CMD_GET_SENSOR_ALTITUDE).await?;
+        Ok(meters)
+    }
+
+    /// Set the ambient pressure used for compensation, overriding the
+    /// altitude
+    ///
+    /// Unlike other settings this can be changed while measuring.
+    pub async fn set_ambient_pressure(&mut self, pascals: u32) -> Result<(), Scd4xError> {
+        let hectopascals = (pascals / 100).min(u16::MAX as u32) as u16;
+        self.command(CMD_SET_AMBIENT_PRESSURE, &[hectopascals], COMMAND_DELAY_MS)
+            .await
+    }
+
+    /// Recalibrate so the current reading becomes `target_ppm` (idle only)
+    ///
+    /// The sensor must have been measuring for at least 3 minutes in the
+    /// target concentration before it was stopped. Returns the correction
+    /// applied, in ppm.
+    pub async fn perform_forced_recalibration(
+        &mut self,
+        target_ppm: u16,
+    ) -> Result<i16, Scd4xError> {
+        self.write_command(CMD_PERFORM_FORCED_RECALIBRATION, &[target_ppm])
+            .await?;
+        Timer::after_millis(FORCED_RECALIBRATION_DELAY_MS).await;
+        let [correction] = self.read_response().await?;
+        if correction == FRC_FAILED {
+            return Err(Scd4xError::RecalibrationFailed);
+        }
+        Ok((correction as i32 - FRC_CORRECTION_OFFSET) as i16)
+    }
+
+    /// Enable or disable automatic self-calibration (idle only)
+    ///
+    /// The setting is lost at power-off.
+    pub async fn set_automatic_self_calibration(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), Scd4xError> {
+        self.command(CMD_SET_ASC_ENABLED, &[enabled as u16], COMMAND_DELAY_MS)
+            .await
+    }
+
+    pub async fn get_automatic_self_calibration(&mut self) -> Result<bool, Scd4xError> {
+        let [enabled] = self.read_words(CMD_GET_ASC_ENABLED).await?;
+        Ok(enabled != 0)
+    }
+
+    /// Get the sensor's 48-bit serial number (idle only)
+    pub async fn get_serial_number(&mut self) -> Result<u64, Scd4xError> {
+        let words: [u16; 3] = self.read_words(CMD_GET_SERIAL_NUMBER).await?;
+        Ok(words
+            .iter()
+            .fold(0u64, |serial, &word| (serial << 16) | word as u64))
+    }
+
+    /// Reload the settings from EEPROM (idle only)
+    pub async fn reinit(&mut self) -> Result<(), Scd4xError> {
+        self.command(CMD_REINIT, &[], REINIT_DELAY_MS).await
+    }
+
+    /// Send a command with arguments and wait `delay_ms` for it to execute
+    async fn command(
+        &mut self,
+        command: u16,
+        args: &[u16],
+        delay_ms: u64,
+    ) -> Result<(), Scd4xError> {
+        self.write_command(command, args).await?;
+        Timer::after_millis(delay_ms).await;
+        Ok(())
+    }
+
+    /// Send a command without arguments and read its `N`-word response
+    async fn read_words<const N: usize>(&mut self, command: u16) -> Result<[u16; N], Scd4xError> {
+        self.write_command(command, &[]).await?;
+        Timer::after_millis(COMMAND_DELAY_MS).await;
+        self.read_response().await
+    }
+
+    async fn write_command(&mut self, command: u16, args: &[u16]) -> Result<(), Scd4xError> {
+        let mut buffer = [0u8; 2 + WORD_SIZE * MAX_WORDS];
+        buffer[..2].copy_from_slice(&command.to_be_bytes());
+        let mut len = 2;
+        for arg in args {
+            let bytes = arg.to_be_bytes();
+            buffer[len..len + 2].copy_from_slice(&bytes);
+            buffer[len + 2] = crc8(&bytes);
+            len += WORD_SIZE;
+        }
+        with_timeout(self.timeout, self.i2c.write(self.address, &buffer[..len]))
+            .await
+            .map_err(|_| Scd4xError::Timeout)?
+            .map_err(|_| Scd4xError::I2cError)
+    }
+
+    async fn read_response<const N: usize>(&mut self) -> Result<[u16; N], Scd4xError> {
+        let mut read_buf = [0u8; WORD_SIZE * MAX_WORDS];
+        let read_buf = &mut read_buf[..WORD_SIZE * N];
+        with_timeout(self.timeout, self.i2c.read(self.address, read_buf))
+            .await
+            .map_err(|_| Scd4xError::Timeout)?
+            .map_err(|_| Scd4xError::I2cError)?;
+
+        let mut words = [0u16; N];
+        for (word, chunk) in words.iter_mut().zip(read_buf.chunks_exact(WORD_SIZE)) {
+            if crc8(&chunk[0..2]) != chunk[2] {
+                return Err(Scd4xError::CrcError);
+            }
+            *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(words)
+    }
+
+    /// Convert SCD4x ticks to temperature in Celsius
+    /// Formula: °C = -45 + 175 * ticks / 65535
+    fn ticks_to_temperature(ticks: u16) -> f32 {
+        -45.0 + 175.0 * ticks as f32 / 65535.0
+    }
+
+    /// Convert SCD4x ticks to relative humidity percentage
+    /// Formula: %RH = 100 * ticks / 65535
+    fn ticks_to_humidity(ticks: u16) -> f32 {
+        100.0 * ticks as f32 / 65535.0
+    }
+}
+
+impl<I2C: BusRecovery> Scd4x<I2C> {
+    /// Clear the I2C bus after repeated failures.
+    pub fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
@@ -0,0 +1,378 @@
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::sgp41::crc8;
+
+const SEN5X_ADDRESS: u8 = 0x69;
+const SEN66_ADDRESS: u8 = 0x6B;
+
+// Commands
+const CMD_START_MEASUREMENT: u16 = 0x0021;
+const CMD_STOP_MEASUREMENT: u16 = 0x0104;
+const CMD_READ_DATA_READY: u16 = 0x0202;
+const CMD_READ_MEASURED_VALUES_SEN5X: u16 = 0x03C4;
+const CMD_READ_MEASURED_VALUES_SEN66: u16 = 0x0300;
+const CMD_START_FAN_CLEANING: u16 = 0x5607;
+const CMD_READ_DEVICE_STATUS: u16 = 0xD206;
+const CMD_READ_AND_CLEAR_DEVICE_STATUS: u16 = 0xD210;
+const CMD_GET_PRODUCT_NAME: u16 = 0xD014;
+const CMD_GET_SERIAL_NUMBER: u16 = 0xD033;
+const CMD_DEVICE_RESET: u16 = 0xD304;
+
+// Timing, from the datasheets
+const COMMAND_DELAY_MS: u64 = 20;
+const START_MEASUREMENT_DELAY_MS: u64 = 50;
+const SEN5X_STOP_MEASUREMENT_DELAY_MS: u64 = 200;
+const SEN66_STOP_MEASUREMENT_DELAY_MS: u64 = 1000;
+const SEN5X_RESET_DELAY_MS: u64 = 100;
+const SEN66_RESET_DELAY_MS: u64 = 1200;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long the fan runs at full speed after `start_fan_cleaning`.
+pub const FAN_CLEANING_DURATION: Duration = Duration::from_secs(10);
+
+// Reported for a signal that isn't available, e.g. during warm-up or on a
+// SEN50, which has no humidity, temperature or gas sensor.
+const INVALID_UNSIGNED: u16 = 0xFFFF;
+const INVALID_SIGNED: i16 = 0x7FFF;
+
+const WORD_SIZE: usize = 3; // 2 bytes data + 1 CRC
+const MAX_WORDS: usize = 16; // Product name and serial number
+const STRING_WORDS: usize = 16;
+
+/// Sensor family. They share a protocol but differ in address, timing and
+/// the values they report.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sen5xModel {
+    /// SEN50, SEN54 or SEN55. The SEN50 measures PM only and the SEN54 has
+    /// no NOx index.
+    Sen5x,
+    /// SEN66, which adds CO2.
+    Sen66,
+}
+
+impl Sen5xModel {
+    fn address(self) -> u8 {
+        match self {
+            Sen5xModel::Sen5x => SEN5X_ADDRESS,
+            Sen5xModel::Sen66 => SEN66_ADDRESS,
+        }
+    }
+}
+
+/// One reading. Values the sensor doesn't have (yet) are `None`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Sen5xMeasurement {
+    /// Mass concentrations in µg/m³.
+    pub pm1: Option<f32>,
+    pub pm25: Option<f32>,
+    pub pm4: Option<f32>,
+    pub pm10: Option<f32>,
+    /// %RH, compensated by the sensor for its own heating.
+    pub humidity: Option<f32>,
+    /// °C, compensated by the sensor for its own heating.
+    pub temperature: Option<f32>,
+    /// Sensirion gas indices, computed on the sensor.
+    pub voc_index: Option<f32>,
+    pub nox_index: Option<f32>,
+    /// ppm; SEN66 only.
+    pub co2: Option<u16>,
+}
+
+/// A flag from the device status register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sen5xFault {
+    /// Fan speed more than 10% off its target; cleared once it recovers.
+    FanSpeedWarning,
+    /// PM sensor failure (SEN66).
+    PmSensorError,
+    /// CO2 sensor failure (SEN66).
+    Co2SensorError,
+    /// Gas sensor failure.
+    GasSensorError,
+    /// Humidity and temperature sensor failure.
+    RhtError,
+    /// Laser current out of range (SEN5x).
+    LaserFailure,
+    /// Fan stopped or blocked.
+    FanFailure,
+}
+
+impl Sen5xFault {
+    const ALL: [Sen5xFault; 7] = [
+        Sen5xFault::FanSpeedWarning,
+        Sen5xFault::PmSensorError,
+        Sen5xFault::Co2SensorError,
+        Sen5xFault::GasSensorError,
+        Sen5xFault::RhtError,
+        Sen5xFault::LaserFailure,
+        Sen5xFault::FanFailure,
+    ];
+
+    const fn bit(self) -> u32 {
+        match self {
+            Sen5xFault::FanSpeedWarning => 1 << 21,
+            Sen5xFault::PmSensorError => 1 << 11,
+            Sen5xFault::Co2SensorError => 1 << 9,
+            Sen5xFault::GasSensorError => 1 << 7,
+            Sen5xFault::RhtError => 1 << 6,
+            Sen5xFault::LaserFailure => 1 << 5,
+            Sen5xFault::FanFailure => 1 << 4,
+        }
+    }
+}
+
+// Informational; set on the SEN5x while the fan is being cleaned.
+const STATUS_FAN_CLEANING: u32 = 1 << 19;
+
+/// Decoded device status register.
+///
+/// Error flags stay set until `read_and_clear_device_status` or a reset.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Sen5xStatus(u32);
+
+impl Sen5xStatus {
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn contains(&self, fault: Sen5xFault) -> bool {
+        self.0 & fault.bit() != 0
+    }
+
+    /// Whether a fan cleaning is in progress (SEN5x only).
+    pub fn fan_cleaning(&self) -> bool {
+        self.0 & STATUS_FAN_CLEANING != 0
+    }
+
+    /// True if no fault flag is set.
+    pub fn is_ok(&self) -> bool {
+        self.faults().next().is_none()
+    }
+
+    /// The fault flags that are set. Reserved bits are ignored.
+    pub fn faults(&self) -> impl Iterator<Item = Sen5xFault> + '_ {
+        Sen5xFault::ALL
+            .into_iter()
+            .filter(move |fault| self.contains(*fault))
+    }
+}
+
+/// Identification strings, read once at init.
+#[derive(Debug, Clone)]
+pub struct Sen5xInfo {
+    /// E.g. "SEN55".
+    pub product_name: heapless::String<32>,
+    pub serial_number: heapless::String<32>,
+}
+
+pub struct Sen5x<I2C> {
+    i2c: I2C,
+    model: Sen5xModel,
+    timeout: Duration,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Sen5xError {
+    I2cError,
+    CrcError,
+    /// No measurement has completed since the last read.
+    NotReady,
+    Timeout,
+}
+
+impl<I2C> Sen5x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Create a new SEN5x/SEN66 driver instance
+    ///
+    /// Note: The sensor keeps measuring through a reset of the host, and
+    /// most commands are only accepted while it is idle, so call
+    /// `stop_measurement` first.
+    pub fn new(i2c: I2C, model: Sen5xModel) -> Self {
+        Self {
+            i2c,
+            model,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set how long a single I2C transfer may take before failing with
+    /// `Sen5xError::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn model(&self) -> Sen5xModel {
+        self.model
+    }
+
+    /// Start continuous measurement, one reading per second
+    ///
+    /// The fan and laser need a few seconds before PM values are valid, and
+    /// the gas indices take longer still.
+    pub async fn start_measurement(&mut self) -> Result<(), Sen5xError> {
+        self.command(CMD_START_MEASUREMENT, START_MEASUREMENT_DELAY_MS)
+            .await
+    }
+
+    /// Stop measuring and wait until the sensor is idle
+    pub async fn stop_measurement(&mut self) -> Result<(), Sen5xError> {
+        let delay_ms = match self.model {
+            Sen5xModel::Sen5x => SEN5X_STOP_MEASUREMENT_DELAY_MS,
+            Sen5xModel::Sen66 => SEN66_STOP_MEASUREMENT_DELAY_MS,
+        };
+        self.command(CMD_STOP_MEASUREMENT, delay_ms).await
+    }
+
+    /// Check whether a measurement has completed since the last read
+    pub async fn data_ready(&mut self) -> Result<bool, Sen5xError> {
+        let [ready] = self.read_words(CMD_READ_DATA_READY).await?;
+        Ok(ready & 0x00FF != 0)
+    }
+
+    /// Read the latest measurement
+    ///
+    /// Returns `Sen5xError::NotReady` if `data_ready` is false.
+    pub async fn read_measured_values(&mut self) -> Result<Sen5xMeasurement, Sen5xError> {
+        if !self.data_ready().await? {
+            return Err(Sen5xError::NotReady);
+        }
+        let words: [u16; 9] = match self.model {
+            Sen5xModel::Sen5x => {
+                let words: [u16; 8] = self.read_words(CMD_READ_MEASURED_VALUES_SEN5X).await?;
+                let mut padded = [INVALID_UNSIGNED; 9];
+                padded[..8].copy_from_slice(&words);
+                padded
+            }
+            Sen5xModel::Sen66 => self.read_words(CMD_READ_MEASURED_VALUES_SEN66).await?,
+        };
+
+        let unsigned =
+            |word: u16, scale: f32| (word != INVALID_UNSIGNED).then_some(word as f32 / scale);
+        let signed = |word: u16, scale: f32| {
+            let value = word as i16;
+            (value != INVALID_SIGNED).then_some(value as f32 / scale)
+        };
+        Ok(Sen5xMeasurement {
+            pm1: unsigned(words[0], 10.0),
+            pm25: unsigned(words[1], 10.0),
+            pm4: unsigned(words[2], 10.0),
+            pm10: unsigned(words[3], 10.0),
+            humidity: signed(words[4], 100.0),
+            temperature: signed(words[5], 200.0),
+            voc_index: signed(words[6], 10.0),
+            nox_index: signed(words[7], 10.0),
+            co2: (words[8] != INVALID_UNSIGNED).then_some(words[8]),
+        })
+    }
+
+    /// Run the fan at full speed for `FAN_CLEANING_DURATION` to blow out dust
+    ///
+    /// The SEN5x only accepts this while measuring, and reports no PM values
+    /// meanwhile; the SEN66 only while idle. The SEN5x also cleans itself
+    /// weekly while measuring.
+    pub async fn start_fan_cleaning(&mut self) -> Result<(), Sen5xError> {
+        self.command(CMD_START_FAN_CLEANING, COMMAND_DELAY_MS).await
+    }
+
+    pub async fn read_device_status(&mut self) -> Result<Sen5xStatus, Sen5xError> {
+        let [high, low] = self.read_words(CMD_READ_DEVICE_STATUS).await?;
+        Ok(Sen5xStatus::from_bits((high as u32) << 16 | low as u32))
+    }
+
+    /// Read the device status register, then clear its error flags
+    pub async fn read_and_clear_device_status(&mut self) -> Result<Sen5xStatus, Sen5xError> {
+        let [high, low] = self.read_words(CMD_READ_AND_CLEAR_DEVICE_STATUS).await?;
+        Ok(Sen5xStatus::from_bits((high as u32) << 16 | low as u32))
+    }
+
+    /// Get the product name, e.g. "SEN55"
+    pub async fn get_product_name(&mut self) -> Result<heapless::String<32>, Sen5xError> {
+        self.read_string(CMD_GET_PRODUCT_NAME).await
+    }
+
+    pub async fn get_serial_number(&mut self) -> Result<heapless::String<32>, Sen5xError> {
+        self.read_string(CMD_GET_SERIAL_NUMBER).await
+    }
+
+    /// Read the product name and serial number
+    pub async fn get_info(&mut self) -> Result<Sen5xInfo, Sen5xError> {
+        Ok(Sen5xInfo {
+            product_name: self.get_product_name().await?,
+            serial_number: self.get_serial_number().await?,
+        })
+    }
+
+    /// Reset the sensor to its power-on state, which is idle
+    pub async fn device_reset(&mut self) -> Result<(), Sen5xError> {
+        let delay_ms = match self.model {
+            Sen5xModel::Sen5x => SEN5X_RESET_DELAY_MS,
+            Sen5xModel::Sen66 => SEN66_RESET_DELAY_MS,
+        };
+        self.command(CMD_DEVICE_RESET, delay_ms).await
+    }
+
+    /// Read a NUL-terminated ASCII string of up to 32 bytes
+    async fn read_string(&mut self, command: u16) -> Result<heapless::String<32>, Sen5xError> {
+        let words: [u16; STRING_WORDS] = self.read_words(command).await?;
+        let mut string = heapless::String::new();
+        for byte in words.iter().flat_map(|word| word.to_be_bytes()) {
+            if byte == 0 {
+                break;
+            }
+            if byte.is_ascii_graphic() || byte == b' ' {
+                let _ = string.push(byte as char);
+            }
+        }
+        Ok(string)
+    }
+
+    /// Send a command without arguments and wait `delay_ms` for it to execute
+    async fn command(&mut self, command: u16, delay_ms: u64) -> Result<(), Sen5xError> {
+        self.write(&command.to_be_bytes()).await?;
+        Timer::after_millis(delay_ms).await;
+        Ok(())
+    }
+
+    /// Send a command without arguments and read its `N`-word response
+    async fn read_words<const N: usize>(&mut self, command: u16) -> Result<[u16; N], Sen5xError> {
+        self.command(command, COMMAND_DELAY_MS).await?;
+
+        let mut read_buf = [0u8; WORD_SIZE * MAX_WORDS];
+        let read_buf = &mut read_buf[..WORD_SIZE * N];
+        with_timeout(self.timeout, self.i2c.read(self.model.address(), read_buf))
+            .await
+            .map_err(|_| Sen5xError::Timeout)?
+            .map_err(|_| Sen5xError::I2cError)?;
+
+        let mut words = [0u16; N];
+        for (word, chunk) in words.iter_mut().zip(read_buf.chunks_exact(WORD_SIZE)) {
+            if crc8(&chunk[0..2]) != chunk[2] {
+                return Err(Sen5xError::CrcError);
+            }
+            *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(words)
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Sen5xError> {
+        with_timeout(self.timeout, self.i2c.write(self.model.address(), bytes))
+            .await
+            .map_err(|_| Sen5xError::Timeout)?
+            .map_err(|_| Sen5xError::I2cError)
+    }
+}
+
+impl<I2C: BusRecovery> Sen5x<I2C> {
+    /// Clear the I2C bus after repeated failures.
+    pub fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
@@ -1,61 +1,245 @@
+use embassy_futures::join::join;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::Instant;
+use embassy_time::{Duration, Instant, Ticker, Timer};
 
 use static_cell::StaticCell;
 
-use crate::sensors::pms5003t::PmsError;
-use crate::sensors::s8::S8Error;
-use crate::sensors::sgp41::Sgp41Error;
+use crate::config::{CONFIG, Co2Sensor};
+use crate::sensors::bme280::{Bme280, Bme280Chip, Bme280Error};
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::co2_compensation;
+use crate::sensors::command::{SensorCommand, SensorCommandError, SensorCommandResult};
+use crate::sensors::gas_index::{AlgorithmType, GasIndexInternals};
+use crate::sensors::gas_index_store::GasIndexStore;
+use crate::sensors::pms_aggregate;
+use crate::sensors::pms_channels::{self, PmsAgreement, PmsChannel};
+use crate::sensors::pms5003t::{Pms5003t, PmsData, PmsError, PmsMode, PmsStats};
+use crate::sensors::s8::{S8Error, S8Info, S8Status};
+use crate::sensors::scd4x::{Scd4x, Scd4xError, Scd4xMeasurement, Scd4xMode};
+use crate::sensors::sen5x::{
+    self, Sen5x, Sen5xError, Sen5xInfo, Sen5xMeasurement, Sen5xModel, Sen5xStatus,
+};
+use crate::sensors::sgp41::{self, Sgp41Error, Sgp41State};
+use crate::sensors::sht4x::{Sht4x, Sht4xError};
 
 use crate::sensors;
 
 #[derive(Debug, Clone)]
 pub struct SensorData {
-    pub pm1: u16,
-    pub pm25: u16,
-    pub pm10: u16,
-    pub pm03_count: u16,
-    pub pm05_count: u16,
-    pub pm10_count: u16,
-    pub pm25_count: u16,
+    /// PM readings; `None` while the sensor providing them has none, or
+    /// while the O-1PPT's channels disagree.
+    pub pm1_cf1: Option<u16>,
+    pub pm25_cf1: Option<u16>,
+    pub pm10_cf1: Option<u16>,
+    pub pm1: Option<u16>,
+    pub pm25: Option<u16>,
+    /// PM2.5 after `CONFIG.sensor.pm25_correction`, if enabled.
+    pub pm25_corrected: Option<f32>,
+    pub pm10: Option<u16>,
+    /// PM4.0 in µg/m³, from the SEN5x/SEN66.
+    pub pm4: Option<u16>,
+    pub pm03_count: Option<u16>,
+    pub pm05_count: Option<u16>,
+    pub pm10_count: Option<u16>,
+    pub pm25_count: Option<u16>,
+    /// PM5.0 and PM10 counts per 0.1 L, from a PMS7003/PMSA003.
+    pub pm50_count: Option<u16>,
+    pub pm100_count: Option<u16>,
+    /// CO2 in ppm, from whichever CO2 sensor is fitted. S8 readings are
+    /// compensated for `pressure`, or the configured site pressure.
     pub co2: u16,
-    pub voc: i32,
-    pub nox: i32,
-    pub temp: f32,
-    pub humidity: f32,
+    /// S8 reading before pressure compensation.
+    pub co2_raw: Option<u16>,
+    /// Factor the S8 reading was multiplied by, if it was compensated.
+    pub co2_compensation_factor: Option<f32>,
+    /// Barometric pressure in Pa, from the BME280/BMP280.
+    pub pressure: Option<f32>,
+    /// BME280/BMP280 temperature and humidity (BME280 only), measured on the
+    /// board rather than in ambient air.
+    pub bme_temp: Option<f32>,
+    pub bme_humidity: Option<f32>,
+    /// Chip found at boot, if any.
+    pub bme_chip: Option<Bme280Chip>,
+    /// S8 meter status flags read with `co2`.
+    pub s8_status: Option<S8Status>,
+    /// S8 ABC period in hours (0 = disabled), read at boot.
+    pub s8_abc_period_hours: Option<u16>,
+    /// Outcome of the last S8 background calibration since boot.
+    pub s8_calibration: Option<S8Calibration>,
+    /// S8 identification, read at boot.
+    pub s8_info: Option<S8Info>,
+    /// SCD4x serial number, read at boot.
+    pub scd_serial_number: Option<u64>,
+    /// Outcome of the last SCD4x forced recalibration since boot.
+    pub scd_calibration: Option<Scd4xCalibration>,
+    /// SGP41 serial number, read at boot.
+    pub sgp_serial_number: Option<u64>,
+    /// SHT4x serial number, read at boot; `None` if no SHT4x answered.
+    pub sht_serial_number: Option<u32>,
+    /// SEN5x/SEN66 product name and serial number, read at boot.
+    pub sen_info: Option<Sen5xInfo>,
+    /// SEN5x/SEN66 device status, read with each measurement.
+    pub sen_status: Option<Sen5xStatus>,
+    /// VOC and NOx indices; `None` until the sensor providing them has a
+    /// reading.
+    pub voc: Option<i32>,
+    pub nox: Option<i32>,
+    /// SGP41 raw signals behind `voc` and `nox`, in ticks.
+    pub sraw_voc: Option<u16>,
+    pub sraw_nox: Option<u16>,
+    /// Gas index algorithm state after processing `sraw_voc`/`sraw_nox`.
+    pub voc_internals: Option<GasIndexInternals>,
+    pub nox_internals: Option<GasIndexInternals>,
+    /// Ambient temperature: from the SHT4x if fitted, otherwise the SEN5x/SEN66
+    /// or the PMS5003T reading compensated for self-heating. `None` if no
+    /// fitted sensor measures it.
+    pub temp: Option<f32>,
+    /// Ambient relative humidity, from the same sensor as `temp`.
+    pub humidity: Option<f32>,
+    /// Temperature as measured inside the PMS5003T housing.
+    pub raw_temp: Option<f32>,
+    /// Relative humidity as measured inside the PMS5003T housing.
+    pub raw_humidity: Option<f32>,
+    pub pms_a: PmsChannel,
+    /// Second PMS sensor, on models that have one.
+    pub pms_b: Option<PmsChannel>,
+    /// Only present when both PMS channels reported.
+    pub pms_agreement: Option<PmsAgreement>,
+    /// Whether the boot-time sensor setup has finished, whether or not the
+    /// SGP41 came up.
     pub initialized: bool,
+    pub sgp_state: Sgp41State,
+    /// Result word of the last SGP41 self-test; `sgp41::SELF_TEST_OK` is a pass.
+    pub sgp_self_test: Option<u16>,
+    /// Whether the SGP41 heater is on, if it follows a schedule.
+    pub sgp_heater_on: Option<bool>,
     pub errors: Option<SensorErrors>,
+    /// Bus resets since boot.
+    pub recoveries: SensorRecoveries,
     pub last_updated: Instant,
 }
 
 impl Default for SensorData {
     fn default() -> Self {
         Self {
-            pm1: 0,
-            pm25: 0,
-            pm10: 0,
-            pm03_count: 0,
-            pm05_count: 0,
-            pm10_count: 0,
-            pm25_count: 0,
+            pm1_cf1: None,
+            pm25_cf1: None,
+            pm10_cf1: None,
+            pm1: None,
+            pm25: None,
+            pm25_corrected: None,
+            pm10: None,
+            pm4: None,
+            pm03_count: None,
+            pm05_count: None,
+            pm10_count: None,
+            pm25_count: None,
+            pm50_count: None,
+            pm100_count: None,
             co2: 0,
-            voc: 0,
-            nox: 0,
-            temp: 0.0,
-            humidity: 0.0,
+            co2_raw: None,
+            co2_compensation_factor: None,
+            pressure: None,
+            bme_temp: None,
+            bme_humidity: None,
+            bme_chip: None,
+            s8_status: None,
+            s8_abc_period_hours: None,
+            s8_calibration: None,
+            s8_info: None,
+            scd_serial_number: None,
+            scd_calibration: None,
+            sgp_serial_number: None,
+            sht_serial_number: None,
+            sen_info: None,
+            sen_status: None,
+            voc: None,
+            nox: None,
+            sraw_voc: None,
+            sraw_nox: None,
+            voc_internals: None,
+            nox_internals: None,
+            temp: None,
+            humidity: None,
+            raw_temp: None,
+            raw_humidity: None,
+            pms_a: PmsChannel::default(),
+            pms_b: None,
+            pms_agreement: None,
             initialized: false,
+            sgp_state: Sgp41State::Uninitialized,
+            sgp_self_test: None,
+            sgp_heater_on: None,
             errors: None,
+            recoveries: SensorRecoveries::default(),
             last_updated: Instant::now(),
         }
     }
 }
 
+/// Result of an S8 background calibration.
+#[derive(Debug, Clone, Copy)]
+pub struct S8Calibration {
+    pub succeeded: bool,
+    /// Uptime when the calibration finished.
+    pub uptime_secs: u64,
+}
+
+/// Result of an SCD4x forced recalibration.
+#[derive(Debug, Clone, Copy)]
+pub struct Scd4xCalibration {
+    /// Correction applied in ppm; `None` if the sensor rejected it.
+    pub correction_ppm: Option<i16>,
+    /// Uptime when the recalibration finished.
+    pub uptime_secs: u64,
+}
+
 #[derive(Debug, Clone, Copy)] // Copy is cheap!
 pub struct SensorErrors {
     pub pms: Option<PmsError>,
+    pub pms_b: Option<PmsError>,
     pub sgp: Option<Sgp41Error>,
+    pub sht: Option<Sht4xError>,
     pub s8: Option<S8Error>,
+    pub scd: Option<Scd4xError>,
+    pub bme: Option<Bme280Error>,
+    pub sen: Option<Sen5xError>,
+}
+
+/// Bus resets per sensor since boot.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SensorRecoveries {
+    pub pms: u32,
+    pub pms_b: u32,
+    pub sgp: u32,
+    pub sht: u32,
+    pub s8: u32,
+    pub scd: u32,
+    pub bme: u32,
+    pub sen: u32,
+}
+
+/// Consecutive failed polls of one sensor.
+#[derive(Default)]
+struct FailureTracker {
+    consecutive: u32,
+}
+
+impl FailureTracker {
+    /// Record a poll; returns true when the sensor's bus should be reset.
+    fn record(&mut self, failed: bool) -> bool {
+        if !failed {
+            self.consecutive = 0;
+            return false;
+        }
+        self.consecutive += 1;
+        if self.consecutive >= CONFIG.sensor.recovery_threshold {
+            self.consecutive = 0;
+            return true;
+        }
+        false
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -86,100 +270,1174 @@ impl Default for SharedSensorData {
     }
 }
 
+/// Time left at the end of each polling interval for the other sensors, when
+/// PMS frames are being collected.
+const PMS_COLLECT_MARGIN: Duration = Duration::from_millis(500);
+
+/// Delay before retrying a failed SGP41 init, doubling on each failure...
+const SGP_RETRY_INITIAL: Duration = Duration::from_secs(10);
+/// ...up to this.
+const SGP_RETRY_MAX: Duration = Duration::from_secs(600);
+
+/// When to retry a failed SGP41 init.
+#[derive(Debug, Clone, Copy)]
+struct SgpRetry {
+    at: Instant,
+    delay: Duration,
+    /// Reported as the SGP41 error until the next attempt.
+    error: Sgp41Error,
+}
+
+/// Where the PMS5003T sensors are in their duty cycle.
+#[derive(Debug, Clone, Copy)]
+enum PmsPhase {
+    /// Fan is running but the airflow has not stabilized yet.
+    WarmingUp { since: Instant },
+    /// Readings are taken on every poll.
+    Sampling { since: Instant },
+    /// Fan and laser are off.
+    Sleeping { since: Instant },
+}
+
+/// Where the SGP41 is in its heater duty cycle.
+#[derive(Debug, Clone, Copy)]
+enum SgpHeaterPhase {
+    On {
+        since: Instant,
+    },
+    /// Quiet period; heater off and nothing measured.
+    Off {
+        since: Instant,
+    },
+}
+
+/// What to do with each PMS5003T on this poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PmsAction {
+    /// Asleep or warming up; nothing to read.
+    Idle,
+    Wakeup,
+    /// Warm-up finished; restore the reporting mode and read.
+    StartSampling,
+    Read,
+    /// Sampling window finished; read one last time and go to sleep.
+    ReadAndSleep,
+}
+
 pub struct SensorManager<I2C, UART0, UART1> {
     sgp: sensors::sgp41::Sgp41<I2C>,
-    pms: sensors::pms5003t::Pms5003t<UART0>,
-    s8: sensors::s8::S8<UART1>,
+    pms: Pms5003t<UART0>,
+    /// Second PMS5003T (O-1PPT), sharing UART1 with the S8 on other models.
+    pms_b: Option<Pms5003t<UART1>>,
+    s8: Option<sensors::s8::S8<UART1>>,
+    /// SHT4x on the SGP41's bus.
+    sht: Option<Sht4x<I2C>>,
+    /// SCD4x on the SGP41's bus, in place of the S8.
+    scd: Option<Scd4x<I2C>>,
+    scd_serial_number: Option<u64>,
+    scd_calibration: Option<Scd4xCalibration>,
+    /// When the SCD4x's next measurement is due; `None` if it isn't
+    /// measuring.
+    scd_ready_at: Option<Instant>,
+    /// Last SCD4x reading, reported until the next one is ready.
+    last_scd: Option<Scd4xMeasurement>,
+    /// BME280/BMP280 on the SGP41's bus.
+    bme: Option<Bme280<I2C>>,
+    /// SEN5x/SEN66 on the same bus, in place of the PMS5003T, SGP41 and S8.
+    sen: Option<Sen5x<I2C>>,
+    sen_info: Option<Sen5xInfo>,
+    /// Whether the SEN5x/SEN66 has been told to measure.
+    sen_measuring: bool,
+    /// Last SEN5x/SEN66 reading, reported until the next one is ready.
+    last_sen: Option<Sen5xMeasurement>,
+    s8_abc_period_hours: Option<u16>,
+    s8_calibration: Option<S8Calibration>,
+    s8_info: Option<S8Info>,
+    sgp_serial_number: Option<u64>,
+    sht_serial_number: Option<u32>,
+    sgp_self_test: Option<u16>,
+    /// Set while the SGP41 is down after a failed init.
+    sgp_retry: Option<SgpRetry>,
+    sgp_heater: SgpHeaterPhase,
+    initialized: bool,
+    gas_index_store: Option<GasIndexStore>,
+    last_gas_index_save: Option<Instant>,
+    pms_phase: PmsPhase,
+    /// Last good PMS readings, reported while the sensors are not sampling.
+    last_pms: Option<PmsData>,
+    last_pms_b: Option<PmsData>,
+    pms_failures: FailureTracker,
+    pms_b_failures: FailureTracker,
+    sgp_failures: FailureTracker,
+    sht_failures: FailureTracker,
+    s8_failures: FailureTracker,
+    scd_failures: FailureTracker,
+    bme_failures: FailureTracker,
+    sen_failures: FailureTracker,
+    recoveries: SensorRecoveries,
 }
 
 impl<I2C, UART0, UART1> SensorManager<I2C, UART0, UART1>
 where
-    I2C: embedded_hal_async::i2c::I2c,
-    UART0: embedded_io_async::Read,
-    UART1: embedded_io_async::Read + embedded_io_async::Write,
+    I2C: embedded_hal_async::i2c::I2c + BusRecovery,
+    UART0: embedded_io_async::Read + embedded_io_async::Write + BusRecovery,
+    UART1: embedded_io_async::Read + embedded_io_async::Write + BusRecovery,
 {
-    pub fn new(
-        sgp: sensors::sgp41::Sgp41<I2C>,
-        pms: sensors::pms5003t::Pms5003t<UART0>,
-        s8: sensors::s8::S8<UART1>,
-    ) -> Self {
-        #[allow(clippy::as_conversions)]
-        Self { sgp, pms, s8 }
+    pub fn new(sgp: sensors::sgp41::Sgp41<I2C>, pms: Pms5003t<UART0>) -> Self {
+        Self {
+            sgp,
+            pms,
+            pms_b: None,
+            s8: None,
+            sht: None,
+            scd: None,
+            scd_serial_number: None,
+            scd_calibration: None,
+            scd_ready_at: None,
+            last_scd: None,
+            bme: None,
+            sen: None,
+            sen_info: None,
+            sen_measuring: false,
+            last_sen: None,
+            s8_abc_period_hours: None,
+            s8_calibration: None,
+            s8_info: None,
+            sgp_serial_number: None,
+            sht_serial_number: None,
+            sgp_self_test: None,
+            sgp_retry: None,
+            sgp_heater: SgpHeaterPhase::On {
+                since: Instant::now(),
+            },
+            initialized: false,
+            gas_index_store: None,
+            last_gas_index_save: None,
+            // The sensor is running from power-on, so treat boot as a wakeup.
+            pms_phase: PmsPhase::WarmingUp {
+                since: Instant::now(),
+            },
+            last_pms: None,
+            last_pms_b: None,
+            pms_failures: FailureTracker::default(),
+            pms_b_failures: FailureTracker::default(),
+            sgp_failures: FailureTracker::default(),
+            sht_failures: FailureTracker::default(),
+            s8_failures: FailureTracker::default(),
+            scd_failures: FailureTracker::default(),
+            bme_failures: FailureTracker::default(),
+            sen_failures: FailureTracker::default(),
+            recoveries: SensorRecoveries::default(),
+        }
+    }
+
+    /// Add a SenseAir S8 CO2 sensor.
+    pub fn with_s8(mut self, s8: sensors::s8::S8<UART1>) -> Self {
+        self.s8 = Some(s8);
+        self
+    }
+
+    /// Add a Sensirion SCD4x CO2 sensor, in place of the S8.
+    pub fn with_scd4x(mut self, scd: Scd4x<I2C>) -> Self {
+        self.scd = Some(scd);
+        self
+    }
+
+    /// Add a BME280 or BMP280 for barometric pressure, which S8 readings are
+    /// compensated for and the SCD4x is given. Dropped at init if it doesn't
+    /// answer.
+    pub fn with_bme280(mut self, bme: Bme280<I2C>) -> Self {
+        self.bme = Some(bme);
+        self
+    }
+
+    /// Use a Sensirion SEN5x/SEN66 in place of the PMS5003T, SGP41 and S8,
+    /// which are then left alone.
+    pub fn with_sen5x(mut self, sen: Sen5x<I2C>) -> Self {
+        self.sen = Some(sen);
+        self
+    }
+
+    /// Add a second PMS5003T, reported as channel "b".
+    pub fn with_second_pms(mut self, pms: Pms5003t<UART1>) -> Self {
+        self.pms_b = Some(pms);
+        self
+    }
+
+    /// Add an SHT4x, preferred over the PMS5003T for temperature, humidity
+    /// and SGP41 compensation. Dropped at init if it doesn't answer.
+    pub fn with_sht(mut self, sht: Sht4x<I2C>) -> Self {
+        self.sht = Some(sht);
+        self
+    }
+
+    /// Persist the SGP41 VOC baseline in `store` per
+    /// `CONFIG.sensor.sgp41_state`.
+    pub fn with_gas_index_store(mut self, store: GasIndexStore) -> Self {
+        self.gas_index_store = Some(store);
+        self
+    }
+
+    /// Configure the sensors and condition the SGP41, publishing its progress
+    /// to `shared`.
+    ///
+    /// An SGP41 failure doesn't stop the other sensors; init is retried from
+    /// `read_and_update`. With a SEN5x/SEN66 there is nothing to condition.
+    pub async fn init(&mut self, shared: &SharedSensorData) {
+        self.init_sen().await;
+
+        if self.sen.is_none() && CONFIG.sensor.pms_passive_mode {
+            if let Err(e) = self.pms.set_mode(PmsMode::Passive).await {
+                defmt::info!(
+                    "PMS: Failed to enter passive mode: {:?}",
+                    defmt::Debug2Format(&e)
+                );
+            }
+            if let Some(pms_b) = self.pms_b.as_mut()
+                && let Err(e) = pms_b.set_mode(PmsMode::Passive).await
+            {
+                defmt::info!(
+                    "PMS B: Failed to enter passive mode: {:?}",
+                    defmt::Debug2Format(&e)
+                );
+            }
+        }
+
+        if let Some(s8) = self.s8.as_mut() {
+            match s8.get_info().await {
+                Ok(info) => self.s8_info = Some(info),
+                Err(e) => defmt::info!("S8: Failed to read info: {:?}", defmt::Debug2Format(&e)),
+            }
+            self.s8_abc_period_hours = Self::init_s8_abc(s8).await;
+        }
+
+        self.init_scd().await;
+
+        if self.sen.is_none() {
+            match self.sgp.get_serial_number().await {
+                Ok(serial) => self.sgp_serial_number = Some(serial),
+                Err(e) => defmt::info!(
+                    "SGP41: Failed to read serial number: {:?}",
+                    defmt::Debug2Format(&e)
+                ),
+            }
+        }
+
+        if let Some(sht) = self.sht.as_mut() {
+            match sht.get_serial_number().await {
+                Ok(serial) => self.sht_serial_number = Some(serial),
+                Err(e) => {
+                    defmt::info!(
+                        "SHT4x: Not found, using PMS temperature and humidity: {:?}",
+                        defmt::Debug2Format(&e)
+                    );
+                    self.sht = None;
+                }
+            }
+        }
+
+        if let Some(bme) = self.bme.as_mut() {
+            match bme.init().await {
+                Ok(chip) => defmt::info!("BME280: Found {}", chip.name()),
+                Err(e) => {
+                    defmt::info!(
+                        "BME280: Not found, CO2 won't be pressure compensated: {:?}",
+                        defmt::Debug2Format(&e)
+                    );
+                    self.bme = None;
+                }
+            }
+        }
+
+        if self.sen.is_none() {
+            self.restore_gas_index();
+            self.init_sgp(shared).await;
+        }
+        self.initialized = true;
+    }
+
+    /// Stop any measurement left running from before a reset and read the
+    /// SEN5x/SEN66's identification; measurement starts on the first poll.
+    async fn init_sen(&mut self) {
+        let Some(sen) = self.sen.as_mut() else {
+            return;
+        };
+
+        if let Err(e) = sen.stop_measurement().await {
+            defmt::info!(
+                "SEN5x: Failed to stop measurement: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+        match sen.get_info().await {
+            Ok(info) => {
+                defmt::info!("SEN5x: Found {}", info.product_name.as_str());
+                self.sen_info = Some(info);
+            }
+            Err(e) => defmt::info!("SEN5x: Failed to read info: {:?}", defmt::Debug2Format(&e)),
+        }
+    }
+
+    /// Read the SEN5x/SEN66 into `data`'s PM, temperature, humidity, gas
+    /// index and (SEN66) CO2 fields, starting measurement if it isn't
+    /// running. The last reading is reported until the next one is ready.
+    /// Status error flags are cleared as they're read, so a fault clears
+    /// once the sensor stops raising it.
+    async fn read_sen(&mut self, data: &mut SensorData) -> Option<Result<(), Sen5xError>> {
+        let sen = self.sen.as_mut()?;
+        if !self.sen_measuring {
+            if let Err(e) = sen.start_measurement().await {
+                return Some(Err(e));
+            }
+            self.sen_measuring = true;
+        }
+
+        let result = match sen.read_and_clear_device_status().await {
+            Ok(status) => {
+                if !status.is_ok() {
+                    defmt::info!("SEN5x: Device status {:#010x}", status.bits());
+                }
+                data.sen_status = Some(status);
+                sen.read_measured_values().await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(reading) => self.last_sen = Some(reading),
+            Err(Sen5xError::NotReady) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        let Some(reading) = self.last_sen else {
+            return Some(Err(Sen5xError::NotReady));
+        };
+
+        let ugm3 = |value: f32| (value + 0.5) as u16;
+        data.pm1 = reading.pm1.map(ugm3);
+        data.pm25 = reading.pm25.map(ugm3);
+        data.pm4 = reading.pm4.map(ugm3);
+        data.pm10 = reading.pm10.map(ugm3);
+        if reading.temperature.is_some() && reading.humidity.is_some() {
+            data.temp = reading.temperature;
+            data.humidity = reading.humidity;
+        }
+        let index = |value: f32| (value + 0.5) as i32;
+        data.voc = reading.voc_index.map(index);
+        data.nox = reading.nox_index.map(index);
+        if matches!(CONFIG.sensor.co2_sensor, Some(Co2Sensor::Sen66))
+            && let Some(co2) = reading.co2
+        {
+            data.co2 = co2;
+        }
+        Some(Ok(()))
+    }
+
+    /// Stop any measurement left running from before a reset and apply the
+    /// configured settings; measurement starts on the first poll.
+    async fn init_scd(&mut self) {
+        let Some(scd) = self.scd.as_mut() else {
+            return;
+        };
+        let config = CONFIG.sensor.scd4x;
+
+        if let Err(e) = scd.stop_periodic_measurement().await {
+            defmt::info!(
+                "SCD4x: Failed to stop measurement: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+        match scd.get_serial_number().await {
+            Ok(serial) => self.scd_serial_number = Some(serial),
+            Err(e) => defmt::info!(
+                "SCD4x: Failed to read serial number: {:?}",
+                defmt::Debug2Format(&e)
+            ),
+        }
+        if let Some(meters) = config.altitude_meters
+            && let Err(e) = scd.set_sensor_altitude(meters).await
+        {
+            defmt::info!(
+                "SCD4x: Failed to set altitude: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+        if let Some(enabled) = config.asc
+            && let Err(e) = scd.set_automatic_self_calibration(enabled).await
+        {
+            defmt::info!("SCD4x: Failed to set ASC: {:?}", defmt::Debug2Format(&e));
+        }
+    }
+
+    /// Read the SCD4x if a measurement is due, (re)starting measurement if it
+    /// isn't running, and return the latest CO2 reading.
+    ///
+    /// `pressure` in Pa, if known, is passed on for the sensor's own
+    /// compensation.
+    async fn read_scd(&mut self, pressure: Option<f32>) -> Option<Result<u16, Scd4xError>> {
+        let scd = self.scd.as_mut()?;
+        let mode = CONFIG.sensor.scd4x.mode;
+        let now = Instant::now();
+        let due = self.scd_ready_at.is_none_or(|at| now >= at);
+        if due
+            && let Some(pressure) = pressure
+            && let Err(e) = scd.set_ambient_pressure(pressure as u32).await
+        {
+            return Some(Err(e));
+        }
+        match self.scd_ready_at {
+            None => {
+                if let Err(e) = scd.start(mode).await {
+                    return Some(Err(e));
+                }
+                self.scd_ready_at = Some(now + mode.measurement_time());
+            }
+            // A single shot doesn't answer until it's done.
+            Some(at) if now >= at => match scd.read_measurement().await {
+                Ok(reading) => {
+                    self.last_scd = Some(reading);
+                    if mode == Scd4xMode::SingleShot {
+                        self.scd_ready_at = None;
+                    }
+                }
+                Err(Scd4xError::NotReady) => {}
+                Err(e) => return Some(Err(e)),
+            },
+            Some(_) => {}
+        }
+        Some(
+            self.last_scd
+                .map(|reading| reading.co2)
+                .ok_or(Scd4xError::NotReady),
+        )
+    }
+
+    /// Self-test and condition the SGP41, scheduling a retry with backoff if
+    /// either fails.
+    async fn init_sgp(&mut self, shared: &SharedSensorData) {
+        let result = match self.sgp.init().await {
+            Ok(()) => {
+                self.sgp_self_test = Some(sgp41::SELF_TEST_OK);
+                self.condition_sgp(shared).await
+            }
+            Err(e) => {
+                if let Sgp41Error::SelfTestFailed(code) = e {
+                    self.sgp_self_test = Some(code);
+                }
+                Err(e)
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                if self.sgp_retry.take().is_some() {
+                    defmt::info!("SGP41: Initialized after retrying");
+                }
+            }
+            Err(error) => {
+                let delay = match self.sgp_retry {
+                    Some(retry) => (retry.delay * 2).min(SGP_RETRY_MAX),
+                    None => SGP_RETRY_INITIAL,
+                };
+                defmt::info!(
+                    "SGP41: Init failed: {:?}, retrying in {} s",
+                    defmt::Debug2Format(&error),
+                    delay.as_secs()
+                );
+                if matches!(error, Sgp41Error::I2cError | Sgp41Error::Timeout) {
+                    self.sgp.recover();
+                    self.recoveries.sgp += 1;
+                }
+                self.sgp_retry = Some(SgpRetry {
+                    at: Instant::now() + delay,
+                    delay,
+                    error,
+                });
+                shared.lock().await.sgp_state = self.sgp.state();
+            }
+        }
+    }
+
+    /// Restore the saved VOC baseline if it is recent enough.
+    fn restore_gas_index(&mut self) {
+        let (Some(store), Some(persistence)) =
+            (self.gas_index_store.as_mut(), CONFIG.sensor.sgp41_state)
+        else {
+            return;
+        };
+        let Some(saved) = store.load() else {
+            defmt::info!("SGP41: No saved gas index state");
+            return;
+        };
+
+        let restore = match saved.age {
+            Some(age) => age <= persistence.max_age,
+            None => persistence.restore_unknown_age,
+        };
+        if restore {
+            defmt::info!(
+                "SGP41: Restoring gas index state (age: {:?})",
+                defmt::Debug2Format(&saved.age)
+            );
+            self.sgp.set_voc_states(saved.states);
+        } else {
+            defmt::info!(
+                "SGP41: Discarding saved gas index state (age: {:?})",
+                defmt::Debug2Format(&saved.age)
+            );
+        }
+    }
+
+    /// Save the VOC baseline if it has been learned and the save interval
+    /// has passed.
+    fn save_gas_index(&mut self) {
+        let (Some(store), Some(persistence)) =
+            (self.gas_index_store.as_mut(), CONFIG.sensor.sgp41_state)
+        else {
+            return;
+        };
+        if self
+            .last_gas_index_save
+            .is_some_and(|last| last.elapsed() < persistence.save_interval)
+        {
+            return;
+        }
+        let Some(states) = self.sgp.voc_states() else {
+            return;
+        };
+
+        // Also on failure, so a bad flash isn't retried every poll.
+        self.last_gas_index_save = Some(Instant::now());
+        if let Err(e) = store.save(states) {
+            defmt::info!(
+                "SGP41: Failed to save gas index state: {:?}",
+                defmt::Debug2Format(&e)
+            );
+        }
+    }
+
+    /// Run the SGP41 conditioning loop, compensated with an SHT4x or PMS
+    /// reading if one is available.
+    async fn condition_sgp(&mut self, shared: &SharedSensorData) -> Result<(), Sgp41Error> {
+        let sht_reading = match self.sht.as_mut() {
+            Some(sht) => sht.measure().await.ok(),
+            None => None,
+        };
+        let (humidity, temp) = match sht_reading {
+            Some(sht) => (Some(sht.humidity), Some(sht.temperature)),
+            None => match self.pms.read().await {
+                Ok(pms) => (pms.compensated_humidity(), pms.compensated_temp()),
+                Err(_) => (None, None),
+            },
+        };
+
+        let mut ticker = Ticker::every(sgp41::CONDITIONING_INTERVAL);
+        loop {
+            shared.lock().await.sgp_state = self.sgp.state();
+            if self.sgp.condition(humidity, temp).await? {
+                break;
+            }
+            ticker.next().await;
+        }
+        shared.lock().await.sgp_state = self.sgp.state();
+        Ok(())
+    }
+
+    /// Apply the configured ABC period if it differs from the sensor's, and
+    /// return the period now in effect.
+    async fn init_s8_abc(s8: &mut sensors::s8::S8<UART1>) -> Option<u16> {
+        let current = match s8.get_abc_period().await {
+            Ok(hours) => hours,
+            Err(e) => {
+                defmt::info!(
+                    "S8: Failed to read ABC period: {:?}",
+                    defmt::Debug2Format(&e)
+                );
+                return None;
+            }
+        };
+
+        let Some(wanted) = CONFIG.sensor.s8_abc_period_hours else {
+            return Some(current);
+        };
+        if wanted == current {
+            return Some(current);
+        }
+
+        defmt::info!("S8: Changing ABC period from {}h to {}h", current, wanted);
+        if let Err(e) = s8.set_abc_period(wanted).await {
+            defmt::info!(
+                "S8: Failed to set ABC period: {:?}",
+                defmt::Debug2Format(&e)
+            );
+            return Some(current);
+        }
+        s8.get_abc_period().await.ok()
     }
 
-    pub async fn init(&mut self) -> Result<(), sensors::sgp41::Sgp41Error> {
-        self.sgp.init().await
+    /// Run a command on behalf of another task.
+    pub async fn execute(
+        &mut self,
+        command: SensorCommand,
+        shared: &SharedSensorData,
+    ) -> SensorCommandResult {
+        match command {
+            SensorCommand::CalibrateS8 => {
+                let s8 = self.s8.as_mut().ok_or(SensorCommandError::NotFitted)?;
+                defmt::info!("S8: Starting background calibration");
+                let result = s8.calibrate_background().await;
+                defmt::info!("S8: Calibration result: {:?}", defmt::Debug2Format(&result));
+                self.s8_calibration = Some(S8Calibration {
+                    succeeded: result.is_ok(),
+                    uptime_secs: Instant::now().as_secs(),
+                });
+                result.map_err(SensorCommandError::S8)
+            }
+            SensorCommand::CalibrateScd4x { target_ppm } => {
+                let scd = self.scd.as_mut().ok_or(SensorCommandError::NotFitted)?;
+                defmt::info!("SCD4x: Starting forced recalibration to {} ppm", target_ppm);
+                if let Some(at) = self.scd_ready_at
+                    && CONFIG.sensor.scd4x.mode == Scd4xMode::SingleShot
+                {
+                    Timer::at(at).await;
+                }
+                let result = match scd.stop_periodic_measurement().await {
+                    Ok(()) => scd.perform_forced_recalibration(target_ppm).await,
+                    Err(e) => Err(e),
+                };
+                defmt::info!(
+                    "SCD4x: Recalibration result: {:?}",
+                    defmt::Debug2Format(&result)
+                );
+                // Restarted on the next poll.
+                self.scd_ready_at = None;
+                self.scd_calibration = Some(Scd4xCalibration {
+                    correction_ppm: result.ok(),
+                    uptime_secs: Instant::now().as_secs(),
+                });
+                result.map(|_| ()).map_err(SensorCommandError::Scd4x)
+            }
+            SensorCommand::CleanSen5xFan => {
+                let sen = self.sen.as_mut().ok_or(SensorCommandError::NotFitted)?;
+                defmt::info!("SEN5x: Starting fan cleaning");
+                let result = match sen.model() {
+                    // Runs while measuring.
+                    Sen5xModel::Sen5x => sen.start_fan_cleaning().await,
+                    Sen5xModel::Sen66 => {
+                        // Restarted on the next poll.
+                        self.sen_measuring = false;
+                        match sen.stop_measurement().await {
+                            Ok(()) => {
+                                let result = sen.start_fan_cleaning().await;
+                                if result.is_ok() {
+                                    Timer::after(sen5x::FAN_CLEANING_DURATION).await;
+                                }
+                                result
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                };
+                defmt::info!(
+                    "SEN5x: Fan cleaning result: {:?}",
+                    defmt::Debug2Format(&result)
+                );
+                result.map_err(SensorCommandError::Sen5x)
+            }
+            // The SGP41 isn't used alongside a SEN5x/SEN66.
+            SensorCommand::SelfTestSgp41 | SensorCommand::TuneGasIndex { .. }
+                if self.sen.is_some() =>
+            {
+                Err(SensorCommandError::NotFitted)
+            }
+            SensorCommand::SelfTestSgp41 => {
+                // The heater schedule and the retry will re-initialize the
+                // sensor themselves.
+                if matches!(self.sgp_heater, SgpHeaterPhase::Off { .. }) || self.sgp_retry.is_some()
+                {
+                    return Err(SensorCommandError::Busy);
+                }
+                // The self-test leaves the heater off, so the sensor needs
+                // conditioning again, as after any init.
+                defmt::info!("SGP41: Re-initializing for self-test");
+                self.init_sgp(shared).await;
+                match self.sgp_retry {
+                    Some(retry) => Err(SensorCommandError::Sgp41(retry.error)),
+                    None => Ok(()),
+                }
+            }
+            SensorCommand::TuneGasIndex { algorithm, update } => {
+                let tuning = update.apply(self.sgp.tuning(algorithm));
+                if !tuning.is_valid(algorithm) {
+                    return Err(SensorCommandError::InvalidParameters);
+                }
+                defmt::info!(
+                    "SGP41: {:?} tuning set to {:?}",
+                    defmt::Debug2Format(&algorithm),
+                    defmt::Debug2Format(&tuning)
+                );
+                self.sgp.set_tuning(algorithm, tuning);
+                Ok(())
+            }
+        }
     }
 
     pub async fn read_and_update(&mut self, shared: &SharedSensorData) {
-        let mut data = self.read_all().await;
-        // Once initialized, keep it true. The SGP41 driver tracks its own state,
-        // but here we ensure the shared data reflects it.
-        data.initialized = self.sgp.is_initialized();
+        if self.sen.is_none() {
+            self.advance_sgp_heater(shared).await;
+        }
+        if self
+            .sgp_retry
+            .is_some_and(|retry| Instant::now() >= retry.at)
+        {
+            defmt::info!("SGP41: Retrying init");
+            self.init_sgp(shared).await;
+        }
+        let data = self.read_all().await;
         shared.update(data).await;
     }
 
+    /// Follow `CONFIG.sensor.sgp41_heater_schedule`: turn the SGP41 heater
+    /// off for the quiet period, then self-test and condition it again.
+    async fn advance_sgp_heater(&mut self, shared: &SharedSensorData) {
+        let Some(schedule) = CONFIG.sensor.sgp41_heater_schedule else {
+            return;
+        };
+        match self.sgp_heater {
+            SgpHeaterPhase::On { since } if since.elapsed() >= schedule.on => {
+                defmt::info!("SGP41: Turning heater off for {} s", schedule.off.as_secs());
+                if let Err(e) = self.sgp.heater_off().await {
+                    // Stay on; the next poll tries again.
+                    defmt::info!(
+                        "SGP41: Failed to turn heater off: {:?}",
+                        defmt::Debug2Format(&e)
+                    );
+                    return;
+                }
+                // A pending init retry waits for the end of the quiet period.
+                self.sgp_retry = None;
+                self.sgp_heater = SgpHeaterPhase::Off {
+                    since: Instant::now(),
+                };
+            }
+            SgpHeaterPhase::Off { since } if since.elapsed() >= schedule.off => {
+                defmt::info!("SGP41: Quiet period over, re-initializing");
+                self.sgp_heater = SgpHeaterPhase::On {
+                    since: Instant::now(),
+                };
+                self.init_sgp(shared).await;
+            }
+            _ => {}
+        }
+    }
+
     async fn read_all(&mut self) -> SensorData {
         let mut data = SensorData::default();
         let mut error_flags = SensorErrors {
             pms: None,
+            pms_b: None,
             sgp: None,
+            sht: None,
             s8: None,
+            scd: None,
+            bme: None,
+            sen: None,
         };
         let mut has_error = false;
 
-        // Read PMS first to get temp/humidity for compensation
-        match self.pms.read().await {
-            Ok(pms_data) => {
-                data.pm1 = pms_data.pm1_ae;
-                data.pm25 = pms_data.pm25_ae;
-                data.pm10 = pms_data.pm10_ae;
-                data.pm03_count = pms_data.pm03_count;
-                data.pm05_count = pms_data.pm05_count;
-                data.pm10_count = pms_data.pm10_count;
-                data.pm25_count = pms_data.pm25_count;
-                data.temp = pms_data.temp;
-                data.humidity = pms_data.humidity;
+        // Read the particle sensors first to get temp/humidity for compensation
+        if self.sen.is_some() {
+            if let Some(Err(e)) = self.read_sen(&mut data).await {
+                error_flags.sen = Some(e);
+                has_error = true;
             }
-            Err(e) => {
+        } else {
+            let action = self.pms_action();
+            let deadline = Instant::now()
+                + CONFIG
+                    .sensor
+                    .polling_interval
+                    .checked_sub(PMS_COLLECT_MARGIN)
+                    .unwrap_or(Duration::from_ticks(0));
+            let ((pms_command, pms_result), pms_b_result) = match self.pms_b.as_mut() {
+                Some(pms_b) => {
+                    let (a, b) = join(
+                        Self::run_pms_action(&mut self.pms, action, deadline),
+                        Self::run_pms_action(pms_b, action, deadline),
+                    )
+                    .await;
+                    (a, Some(b))
+                }
+                None => (
+                    Self::run_pms_action(&mut self.pms, action, deadline).await,
+                    None,
+                ),
+            };
+
+            let (channel, result) =
+                Self::resolve_pms(pms_result, &mut self.last_pms, self.pms.stats());
+            data.pms_a = channel;
+            // A failed command is reported after the reading, if that worked.
+            if let Err(e) = result.and(pms_command) {
                 error_flags.pms = Some(e);
                 has_error = true;
             }
+            let mut command_ok = pms_command.is_ok();
+
+            if let (Some(pms_b), Some((pms_b_command, pms_b_result))) =
+                (self.pms_b.as_ref(), pms_b_result)
+            {
+                let (channel, result) =
+                    Self::resolve_pms(pms_b_result, &mut self.last_pms_b, pms_b.stats());
+                data.pms_b = Some(channel);
+                if let Err(e) = result.and(pms_b_command) {
+                    error_flags.pms_b = Some(e);
+                    has_error = true;
+                }
+                command_ok &= pms_b_command.is_ok();
+            }
+            if command_ok {
+                self.advance_pms_phase(action);
+            }
+
+            let pms_a = data.pms_a.data;
+            let pms_b = data.pms_b.and_then(|channel| channel.data);
+            if let (Some(a), Some(b)) = (&pms_a, &pms_b) {
+                data.pms_agreement = Some(PmsAgreement::between(a, b));
+            }
+            if let Some(pms_data) = pms_channels::combine(pms_a.as_ref(), pms_b.as_ref()) {
+                data.pm1_cf1 = Some(pms_data.pm1_cf1);
+                data.pm25_cf1 = Some(pms_data.pm25_cf1);
+                data.pm10_cf1 = Some(pms_data.pm10_cf1);
+                data.pm1 = Some(pms_data.pm1_ae);
+                data.pm25 = Some(pms_data.pm25_ae);
+                data.pm10 = Some(pms_data.pm10_ae);
+                data.pm03_count = Some(pms_data.pm03_count);
+                data.pm05_count = Some(pms_data.pm05_count);
+                data.pm10_count = Some(pms_data.pm10_count);
+                data.pm25_count = Some(pms_data.pm25_count);
+                data.pm50_count = pms_data.pm50_count;
+                data.pm100_count = pms_data.pm100_count;
+                data.temp = pms_data.compensated_temp();
+                data.humidity = pms_data.compensated_humidity();
+                data.raw_temp = pms_data.temp;
+                data.raw_humidity = pms_data.humidity;
+                // The correction formulas are fitted against the raw housing
+                // humidity, which models other than the PMS5003T lack.
+                data.pm25_corrected = CONFIG
+                    .sensor
+                    .pm25_correction
+                    .and_then(|c| c.apply(pms_data.pm25_cf1 as f32, pms_data.humidity));
+            }
         }
 
-        // Use temp/humidity from PMS for SGP compensation if available
-        match self
-            .sgp
-            .measure_indices(Some(data.humidity), Some(data.temp))
-            .await
-        {
-            Ok((voc_idx, nox_idx)) => {
-                data.voc = voc_idx;
-                data.nox = nox_idx;
+        // The SHT4x sits outside the PMS5003T housing, so it needs no
+        // self-heating compensation.
+        if let Some(sht) = self.sht.as_mut() {
+            match sht.measure().await {
+                Ok(reading) => {
+                    data.temp = Some(reading.temperature);
+                    data.humidity = Some(reading.humidity);
+                }
+                Err(e) => {
+                    error_flags.sht = Some(e);
+                    has_error = true;
+                }
             }
-            Err(e) => {
+        }
+
+        if let Some(bme) = self.bme.as_mut() {
+            match bme.measure().await {
+                Ok(reading) => {
+                    data.pressure = Some(reading.pressure);
+                    data.bme_temp = Some(reading.temperature);
+                    data.bme_humidity = reading.humidity;
+                }
+                Err(e) => {
+                    error_flags.bme = Some(e);
+                    has_error = true;
+                }
+            }
+        }
+
+        // Use ambient temp/humidity for SGP compensation if available
+        let sgp_result = match (self.sgp_heater, self.sgp_retry) {
+            _ if self.sen.is_some() => None,
+            (SgpHeaterPhase::Off { .. }, _) => None,
+            (_, Some(retry)) => Some(Err(retry.error)),
+            _ => Some(self.sgp.measure_indices(data.humidity, data.temp).await),
+        };
+        match sgp_result {
+            None => {}
+            Some(Ok(measurement)) => {
+                data.voc = Some(measurement.voc_index);
+                data.nox = Some(measurement.nox_index);
+                data.sraw_voc = Some(measurement.sraw_voc);
+                data.sraw_nox = Some(measurement.sraw_nox);
+                data.voc_internals = Some(self.sgp.gas_index_internals(AlgorithmType::Voc));
+                data.nox_internals = Some(self.sgp.gas_index_internals(AlgorithmType::Nox));
+                self.save_gas_index();
+            }
+            Some(Err(e)) => {
                 error_flags.sgp = Some(e);
                 has_error = true;
             }
         }
 
-        match self.s8.get_co2().await {
-            Ok(co2) => {
-                data.co2 = co2;
+        data.s8_abc_period_hours = self.s8_abc_period_hours;
+        data.s8_calibration = self.s8_calibration;
+        data.s8_info = self.s8_info;
+        data.sgp_serial_number = self.sgp_serial_number;
+        data.scd_serial_number = self.scd_serial_number;
+        data.scd_calibration = self.scd_calibration;
+        data.sht_serial_number = self.sht_serial_number;
+        data.sen_info = self.sen_info.clone();
+        data.bme_chip = self.bme.as_ref().and_then(|bme| bme.chip());
+        // A barometer reading beats the configured site pressure.
+        let pressure = data
+            .pressure
+            .or_else(|| CONFIG.sensor.site_pressure.map(|site| site.pascals()));
+        if let Some(s8) = self.s8.as_mut() {
+            match s8.read().await {
+                Ok(reading) => {
+                    if !reading.status.is_ok() {
+                        defmt::info!("S8: Meter status {:#06x}", reading.status.bits());
+                    }
+                    data.co2_raw = Some(reading.co2);
+                    data.co2_compensation_factor = pressure.map(co2_compensation::s8_factor);
+                    data.co2 = match data.co2_compensation_factor {
+                        Some(factor) => co2_compensation::apply(reading.co2, factor),
+                        None => reading.co2,
+                    };
+                    data.s8_status = Some(reading.status);
+                }
+                Err(e) => {
+                    error_flags.s8 = Some(e);
+                    has_error = true;
+                }
             }
-            Err(e) => {
-                error_flags.s8 = Some(e);
+        }
+
+        match self.read_scd(pressure).await {
+            Some(Ok(co2)) => data.co2 = co2,
+            Some(Err(e)) => {
+                error_flags.scd = Some(e);
                 has_error = true;
             }
+            None => {}
         }
 
+        self.recover_failed(&error_flags);
+        data.recoveries = self.recoveries;
+
         if has_error {
             data.errors = Some(error_flags);
         }
 
-        data.initialized = self.sgp.is_initialized();
+        data.initialized = self.initialized;
+        data.sgp_state = self.sgp.state();
+        data.sgp_self_test = self.sgp_self_test;
+        data.sgp_heater_on = CONFIG
+            .sensor
+            .sgp41_heater_schedule
+            .filter(|_| self.sen.is_none())
+            .map(|_| matches!(self.sgp_heater, SgpHeaterPhase::On { .. }));
         data.last_updated = Instant::now();
 
         data
     }
+
+    /// Reset the bus of any sensor that has failed too many polls in a row.
+    fn recover_failed(&mut self, errors: &SensorErrors) {
+        let pms_failed = |e: Option<PmsError>| e.is_some_and(|e| !matches!(e, PmsError::Sleeping));
+
+        if self.pms_failures.record(pms_failed(errors.pms)) {
+            defmt::info!("PMS: Repeated failures, resetting UART");
+            self.pms.recover();
+            self.recoveries.pms += 1;
+        }
+        if let Some(pms_b) = self.pms_b.as_mut()
+            && self.pms_b_failures.record(pms_failed(errors.pms_b))
+        {
+            defmt::info!("PMS B: Repeated failures, resetting UART");
+            pms_b.recover();
+            self.recoveries.pms_b += 1;
+        }
+        // Before init succeeds there's no transfer to judge the bus by; a
+        // failed init clears the bus itself.
+        let sgp_failed = self.sgp_retry.is_none()
+            && errors
+                .sgp
+                .is_some_and(|e| !matches!(e, Sgp41Error::NotInitialized));
+        if self.sgp_failures.record(sgp_failed) {
+            defmt::info!("SGP41: Repeated failures, clearing I2C bus");
+            self.sgp.recover();
+            self.recoveries.sgp += 1;
+        }
+        if let Some(sht) = self.sht.as_mut()
+            && self.sht_failures.record(errors.sht.is_some())
+        {
+            defmt::info!("SHT4x: Repeated failures, clearing I2C bus");
+            sht.recover();
+            self.recoveries.sht += 1;
+        }
+        if let Some(s8) = self.s8.as_mut()
+            && self.s8_failures.record(errors.s8.is_some())
+        {
+            defmt::info!("S8: Repeated failures, resetting UART");
+            s8.recover();
+            self.recoveries.s8 += 1;
+        }
+        let scd_failed = errors
+            .scd
+            .is_some_and(|e| !matches!(e, Scd4xError::NotReady));
+        if let Some(scd) = self.scd.as_mut()
+            && self.scd_failures.record(scd_failed)
+        {
+            defmt::info!("SCD4x: Repeated failures, clearing I2C bus");
+            scd.recover();
+            self.recoveries.scd += 1;
+        }
+        if let Some(bme) = self.bme.as_mut()
+            && self.bme_failures.record(errors.bme.is_some())
+        {
+            defmt::info!("BME280: Repeated failures, clearing I2C bus");
+            bme.recover();
+            self.recoveries.bme += 1;
+        }
+        let sen_failed = errors
+            .sen
+            .is_some_and(|e| !matches!(e, Sen5xError::NotReady));
+        if let Some(sen) = self.sen.as_mut()
+            && self.sen_failures.record(sen_failed)
+        {
+            defmt::info!("SEN5x: Repeated failures, clearing I2C bus");
+            sen.recover();
+            self.recoveries.sen += 1;
+        }
+    }
+
+    /// The PMS duty cycle action due on this poll, if a duty cycle is
+    /// configured.
+    fn pms_action(&self) -> PmsAction {
+        let Some(duty_cycle) = CONFIG.sensor.pms_duty_cycle else {
+            return PmsAction::Read;
+        };
+
+        let now = Instant::now();
+        match self.pms_phase {
+            PmsPhase::Sleeping { since } if now.duration_since(since) < duty_cycle.sleep => {
+                PmsAction::Idle
+            }
+            PmsPhase::Sleeping { .. } => PmsAction::Wakeup,
+            PmsPhase::WarmingUp { since } if now.duration_since(since) < duty_cycle.warmup => {
+                PmsAction::Idle
+            }
+            PmsPhase::WarmingUp { .. } => PmsAction::StartSampling,
+            PmsPhase::Sampling { since }
+                if now.duration_since(since) < duty_cycle.sampling_window =>
+            {
+                PmsAction::Read
+            }
+            PmsPhase::Sampling { .. } => PmsAction::ReadAndSleep,
+        }
+    }
+
+    /// Advance the PMS duty cycle once `action`'s command has succeeded on
+    /// every sensor. Until then the phase stays put, so the command is sent
+    /// again on the next poll.
+    fn advance_pms_phase(&mut self, action: PmsAction) {
+        let now = Instant::now();
+        self.pms_phase = match action {
+            PmsAction::Idle | PmsAction::Read => return,
+            PmsAction::Wakeup => {
+                defmt::info!("PMS: Woken up");
+                PmsPhase::WarmingUp { since: now }
+            }
+            PmsAction::StartSampling => PmsPhase::Sampling { since: now },
+            PmsAction::ReadAndSleep => {
+                defmt::info!("PMS: Sleeping");
+                PmsPhase::Sleeping { since: now }
+            }
+        };
+    }
+
+    /// Apply a duty cycle action to one sensor.
+    ///
+    /// Returns the result of the action's command, if any, and `None` if no
+    /// reading was attempted, otherwise the reading and the number of frames
+    /// it combines.
+    async fn run_pms_action<U>(
+        pms: &mut Pms5003t<U>,
+        action: PmsAction,
+        deadline: Instant,
+    ) -> (
+        Result<(), PmsError>,
+        Option<Result<(PmsData, usize), PmsError>>,
+    )
+    where
+        U: embedded_io_async::Read + embedded_io_async::Write,
+    {
+        match action {
+            PmsAction::Idle => (Ok(()), None),
+            PmsAction::Wakeup => (pms.wakeup().await, None),
+            PmsAction::StartSampling => {
+                // Wakeup puts the sensor back in active mode.
+                if CONFIG.sensor.pms_passive_mode
+                    && let Err(e) = pms.set_mode(PmsMode::Passive).await
+                {
+                    return (Err(e), None);
+                }
+                (Ok(()), Some(Self::sample_pms(pms, deadline).await))
+            }
+            PmsAction::Read => (Ok(()), Some(Self::sample_pms(pms, deadline).await)),
+            PmsAction::ReadAndSleep => {
+                let result = Self::sample_pms(pms, deadline).await;
+                (pms.sleep().await, Some(result))
+            }
+        }
+    }
+
+    /// Read one sensor, aggregating every frame received before `deadline` if
+    /// configured.
+    async fn sample_pms<U>(
+        pms: &mut Pms5003t<U>,
+        deadline: Instant,
+    ) -> Result<(PmsData, usize), PmsError>
+    where
+        U: embedded_io_async::Read + embedded_io_async::Write,
+    {
+        let Some(method) = CONFIG.sensor.pms_aggregation else {
+            return pms.read().await.map(|data| (data, 1));
+        };
+
+        let mut frames: heapless::Vec<PmsData, { pms_aggregate::MAX_FRAMES }> =
+            heapless::Vec::new();
+        pms.read_until(deadline, &mut frames).await?;
+        let data =
+            pms_aggregate::aggregate(&frames, method).ok_or(PmsError::MaxAttemptsExceeded)?;
+        Ok((data, frames.len()))
+    }
+
+    /// Build the channel state, falling back to the last good reading while the
+    /// sensor is not sampling.
+    fn resolve_pms(
+        result: Option<Result<(PmsData, usize), PmsError>>,
+        last: &mut Option<PmsData>,
+        stats: PmsStats,
+    ) -> (PmsChannel, Result<PmsData, PmsError>) {
+        let (result, frame_count) = match result {
+            Some(Ok((pms_data, frame_count))) => {
+                *last = Some(pms_data);
+                (Ok(pms_data), frame_count)
+            }
+            Some(Err(e)) => (Err(e), 0),
+            // Not sampling right now; keep reporting the last window's values.
+            None => (last.ok_or(PmsError::Sleeping), 0),
+        };
+        let channel = PmsChannel {
+            data: result.ok(),
+            frame_count,
+            stats,
+        };
+        (channel, result)
+    }
 }
@@ -1,12 +1,19 @@
 const CRC_POLYNOMIAL: u8 = 0x31;
-use gas_index_algorithm::{AlgorithmType, GasIndexAlgorithm};
+use embassy_time::{Duration, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::gas_index::{
+    AlgorithmType, GasIndexAlgorithm, GasIndexInternals, GasIndexStates, TuningParameters,
+};
 const CRC_INIT: u8 = 0xFF;
 const SGP41_ADDRESS: u8 = 0x59;
 
 // Commands
 const CMD_MEASURE_RAW: [u8; 2] = [0x26, 0x19];
+const CMD_EXECUTE_CONDITIONING: [u8; 2] = [0x26, 0x12];
 const CMD_SELF_TEST: [u8; 2] = [0x28, 0x0E];
 const CMD_HEATER_OFF: [u8; 2] = [0x36, 0x15];
+const CMD_GET_SERIAL_NUMBER: [u8; 2] = [0x36, 0x82];
 
 // Default compensation values
 const DEFAULT_RH_TICKS: u16 = 0x8000; // 50% RH
@@ -15,21 +22,79 @@ const DEFAULT_TEMP_TICKS: u16 = 0x6666; // 25°C
 // Measurement timing
 const MEASURE_DELAY_MS: u32 = 50;
 const SELF_TEST_DELAY_MS: u32 = 320; // Self-test takes ~320ms
-const CONDITIONING_DELAY_MS: u32 = 10_000; // 10 seconds recommended
+// Conditioning is one command per second for 10 s; longer may damage the sensor.
+pub const CONDITIONING_INTERVAL: Duration = Duration::from_secs(1);
+pub const CONDITIONING_STEPS: u8 = 10;
+const SERIAL_NUMBER_DELAY_MS: u32 = 1;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 
 // Buffer sizes
 const RESPONSE_SIZE: usize = 6; // 2 bytes data + 1 CRC, twice
+const CONDITIONING_RESPONSE_SIZE: usize = 3; // 2 bytes SRAW_VOC + 1 CRC
 const SELF_TEST_RESPONSE_SIZE: usize = 3; // 2 bytes result + 1 CRC
+const SERIAL_NUMBER_RESPONSE_SIZE: usize = 9; // 2 bytes data + 1 CRC, three times
+
+/// Self-test result word when both pixels pass.
+pub const SELF_TEST_OK: u16 = 0xD400;
+// Pixel failure bits, in the result's LSB; the MSB is don't-care.
+const SELF_TEST_VOC_PIXEL_FAILED: u16 = 1 << 0;
+const SELF_TEST_NOX_PIXEL_FAILED: u16 = 1 << 1;
+
+/// Initialization state of the SGP41.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sgp41State {
+    /// Powered on or heater turned off; `init` has not succeeded yet.
+    Uninitialized,
+    /// Self-test passed; `condition` has run `steps` times.
+    Conditioning { steps: u8 },
+    /// Conditioned and ready for measurements.
+    Ready,
+}
+
+/// Self-test result word.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sgp41SelfTest(u16);
+
+impl Sgp41SelfTest {
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn voc_pixel_ok(self) -> bool {
+        self.0 & SELF_TEST_VOC_PIXEL_FAILED == 0
+    }
 
-// Self-test result codes
-const SELF_TEST_OK: u16 = 0xD400;
+    pub const fn nox_pixel_ok(self) -> bool {
+        self.0 & SELF_TEST_NOX_PIXEL_FAILED == 0
+    }
+
+    pub const fn is_ok(self) -> bool {
+        self.0 == SELF_TEST_OK
+    }
+}
+
+/// Raw signals and the gas indices computed from them.
+#[derive(Debug, Copy, Clone)]
+pub struct Sgp41Measurement {
+    /// SRAW_VOC in ticks.
+    pub sraw_voc: u16,
+    /// SRAW_NOX in ticks.
+    pub sraw_nox: u16,
+    pub voc_index: i32,
+    pub nox_index: i32,
+}
 
 pub struct Sgp41<I2C> {
     i2c: I2C,
     address: u8,
-    initialized: bool,
+    state: Sgp41State,
     voc_algorithm: GasIndexAlgorithm,
     nox_algorithm: GasIndexAlgorithm,
+    timeout: Duration,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -38,6 +103,7 @@ pub enum Sgp41Error {
     CrcError,
     SelfTestFailed(u16), // Contains the actual test result
     NotInitialized,
+    Timeout,
 }
 
 impl<I2C> Sgp41<I2C>
@@ -51,54 +117,92 @@ where
         Self {
             i2c,
             address: SGP41_ADDRESS,
-            initialized: false,
+            state: Sgp41State::Uninitialized,
             voc_algorithm: GasIndexAlgorithm::new(AlgorithmType::Voc, sampling_interval_secs),
             nox_algorithm: GasIndexAlgorithm::new(AlgorithmType::Nox, sampling_interval_secs),
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
-    /// Initialize the sensor with self-test and conditioning
+    /// Set how long a single I2C transfer may take before failing with
+    /// `Sgp41Error::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the gas index tuning parameters
+    pub fn with_tuning(mut self, voc: TuningParameters, nox: TuningParameters) -> Self {
+        self.voc_algorithm.set_tuning_parameters(voc);
+        self.nox_algorithm.set_tuning_parameters(nox);
+        self
+    }
+
+    /// Initialize the sensor with a self-test
     ///
-    /// This performs:
-    /// 1. Self-test to verify sensor functionality
-    /// 2. Conditioning period (10 seconds) to stabilize readings
+    /// On success the sensor is ready for conditioning: call `condition`
+    /// every `CONDITIONING_INTERVAL` until it returns `true`. The first
+    /// measurement should follow within a second of the last step.
     ///
     /// Must be called once after power-on before measurements
     pub async fn init(&mut self) -> Result<(), Sgp41Error> {
-        // Run self-test
+        self.state = Sgp41State::Uninitialized;
         self.self_test().await?;
+        self.state = Sgp41State::Conditioning { steps: 0 };
+        Ok(())
+    }
 
-        // Conditioning: Run measurements for 10 seconds to stabilize
-        // Take one measurement, then wait the remainder of the conditioning time
-        let _ = self.measure_internal(None, None).await?;
-
-        // Wait for conditioning period (minus the measurement time)
-        embassy_time::Timer::after_millis((CONDITIONING_DELAY_MS - MEASURE_DELAY_MS) as u64).await;
+    /// Run one conditioning step with optional temperature and humidity compensation
+    ///
+    /// Conditioning heats the NOx pixel; the VOC pixel measures as usual and
+    /// its raw value is discarded.
+    ///
+    /// # Returns
+    /// * `Ok(true)` - Conditioning is complete and the sensor is ready
+    /// * `Ok(false)` - More steps are needed
+    /// * `Err(Sgp41Error)` - Communication or CRC error, or `init` has not succeeded
+    pub async fn condition(
+        &mut self,
+        humidity: Option<f32>,
+        temp: Option<f32>,
+    ) -> Result<bool, Sgp41Error> {
+        let Sgp41State::Conditioning { steps } = self.state else {
+            return match self.state {
+                Sgp41State::Ready => Ok(true),
+                _ => Err(Sgp41Error::NotInitialized),
+            };
+        };
+
+        let mut read_buf = [0u8; CONDITIONING_RESPONSE_SIZE];
+        self.command_with_compensation(CMD_EXECUTE_CONDITIONING, humidity, temp, &mut read_buf)
+            .await?;
+        Self::validate_crc(&read_buf[0..2], read_buf[2])?;
 
-        self.initialized = true;
-        Ok(())
+        let steps = steps + 1;
+        self.state = if steps >= CONDITIONING_STEPS {
+            Sgp41State::Ready
+        } else {
+            Sgp41State::Conditioning { steps }
+        };
+        Ok(self.state == Sgp41State::Ready)
     }
 
     /// Run the sensor's built-in self-test
     ///
     /// Tests the sensor's heater and measurement circuitry.
     /// Returns Ok if test passes, Err with result code if it fails.
+    ///
+    /// The heater is off afterwards; the next measurement turns it back on.
     pub async fn self_test(&mut self) -> Result<(), Sgp41Error> {
         // Send self-test command
-        self.i2c
-            .write(self.address, &CMD_SELF_TEST)
-            .await
-            .map_err(|_| Sgp41Error::I2cError)?;
+        self.write(&CMD_SELF_TEST).await?;
 
         // Wait for self-test to complete (~320ms)
         embassy_time::Timer::after_millis(SELF_TEST_DELAY_MS as u64).await;
 
         // Read result
         let mut read_buf = [0u8; SELF_TEST_RESPONSE_SIZE];
-        self.i2c
-            .read(self.address, &mut read_buf)
-            .await
-            .map_err(|_| Sgp41Error::I2cError)?;
+        self.read(&mut read_buf).await?;
 
         // Validate CRC
         Self::validate_crc(&read_buf[0..2], read_buf[2])?;
@@ -112,22 +216,43 @@ where
         }
     }
 
+    /// Read the 48-bit serial number
+    ///
+    /// Does not require `init()`.
+    pub async fn get_serial_number(&mut self) -> Result<u64, Sgp41Error> {
+        self.write(&CMD_GET_SERIAL_NUMBER).await?;
+
+        embassy_time::Timer::after_millis(SERIAL_NUMBER_DELAY_MS as u64).await;
+
+        let mut read_buf = [0u8; SERIAL_NUMBER_RESPONSE_SIZE];
+        self.read(&mut read_buf).await?;
+
+        let mut serial = 0u64;
+        for word in read_buf.chunks_exact(3) {
+            Self::validate_crc(&word[0..2], word[2])?;
+            serial = (serial << 16) | u16::from_be_bytes([word[0], word[1]]) as u64;
+        }
+        Ok(serial)
+    }
+
     /// Turn off the heater (for power saving when not in use)
     ///
     /// After calling this, you must call `init()` again before measurements
     pub async fn heater_off(&mut self) -> Result<(), Sgp41Error> {
-        self.i2c
-            .write(self.address, &CMD_HEATER_OFF)
-            .await
-            .map_err(|_| Sgp41Error::I2cError)?;
+        self.write(&CMD_HEATER_OFF).await?;
 
-        self.initialized = false;
+        self.state = Sgp41State::Uninitialized;
         Ok(())
     }
 
     /// Check if the sensor has been initialized
     pub fn is_initialized(&self) -> bool {
-        self.initialized
+        self.state == Sgp41State::Ready
+    }
+
+    /// Initialization state
+    pub fn state(&self) -> Sgp41State {
+        self.state
     }
 
     /// Measure raw VOC and NOx signals with optional temperature and humidity compensation
@@ -147,7 +272,7 @@ where
         humidity: Option<f32>,
         temp: Option<f32>,
     ) -> Result<(u16, u16), Sgp41Error> {
-        if !self.initialized {
+        if !self.is_initialized() {
             return Err(Sgp41Error::NotInitialized);
         }
 
@@ -163,19 +288,65 @@ where
     /// * `temp` - Temperature in °C (-45 to 130). Defaults to 25°C if None.
     ///
     /// # Returns
-    /// * `Ok((voc_index, nox_index))` - Processed index values
+    /// * `Ok(Sgp41Measurement)` - Raw signals and processed index values
     /// * `Err(Sgp41Error)` - Communication, CRC, or initialization error
     pub async fn measure_indices(
         &mut self,
         humidity: Option<f32>,
         temp: Option<f32>,
-    ) -> Result<(i32, i32), Sgp41Error> {
-        let (voc_raw, nox_raw) = self.measure(humidity, temp).await?;
+    ) -> Result<Sgp41Measurement, Sgp41Error> {
+        let (sraw_voc, sraw_nox) = self.measure(humidity, temp).await?;
+
+        Ok(Sgp41Measurement {
+            sraw_voc,
+            sraw_nox,
+            voc_index: self.voc_algorithm.process(sraw_voc as i32),
+            nox_index: self.nox_algorithm.process(sraw_nox as i32),
+        })
+    }
 
-        let voc_index = self.voc_algorithm.process(voc_raw as i32);
-        let nox_index = self.nox_algorithm.process(nox_raw as i32);
+    /// Internal state of the VOC or NOx gas index algorithm
+    pub fn gas_index_internals(&self, algorithm: AlgorithmType) -> GasIndexInternals {
+        self.algorithm(algorithm).internals()
+    }
+
+    /// Learned VOC baseline, once there is enough of it to be worth saving
+    ///
+    /// Only the VOC algorithm supports restoring its state; the NOx index
+    /// relearns from scratch.
+    pub fn voc_states(&self) -> Option<GasIndexStates> {
+        self.voc_algorithm
+            .states_learned()
+            .then(|| self.voc_algorithm.get_states())
+    }
+
+    /// Restore a VOC baseline saved with `voc_states`
+    ///
+    /// Should be called before the first measurement.
+    pub fn set_voc_states(&mut self, states: GasIndexStates) {
+        self.voc_algorithm.set_states(states);
+    }
+
+    /// Tuning parameters of the VOC or NOx gas index algorithm
+    pub fn tuning(&self, algorithm: AlgorithmType) -> TuningParameters {
+        self.algorithm(algorithm).tuning_parameters()
+    }
 
-        Ok((voc_index, nox_index))
+    /// Change the tuning parameters of the VOC or NOx gas index algorithm
+    ///
+    /// This restarts the algorithm's learning, discarding its baseline.
+    pub fn set_tuning(&mut self, algorithm: AlgorithmType, tuning: TuningParameters) {
+        match algorithm {
+            AlgorithmType::Voc => self.voc_algorithm.set_tuning_parameters(tuning),
+            AlgorithmType::Nox => self.nox_algorithm.set_tuning_parameters(tuning),
+        }
+    }
+
+    fn algorithm(&self, algorithm: AlgorithmType) -> &GasIndexAlgorithm {
+        match algorithm {
+            AlgorithmType::Voc => &self.voc_algorithm,
+            AlgorithmType::Nox => &self.nox_algorithm,
+        }
     }
 
     /// Internal measurement function (bypasses initialization check)
@@ -184,6 +355,28 @@ where
         humidity: Option<f32>,
         temp: Option<f32>,
     ) -> Result<(u16, u16), Sgp41Error> {
+        let mut read_buf = [0u8; RESPONSE_SIZE];
+        self.command_with_compensation(CMD_MEASURE_RAW, humidity, temp, &mut read_buf)
+            .await?;
+
+        // Validate CRCs
+        Self::validate_crc(&read_buf[0..2], read_buf[2])?;
+        Self::validate_crc(&read_buf[3..5], read_buf[5])?;
+
+        let voc_raw = u16::from_be_bytes([read_buf[0], read_buf[1]]);
+        let nox_raw = u16::from_be_bytes([read_buf[3], read_buf[4]]);
+
+        Ok((voc_raw, nox_raw))
+    }
+
+    /// Send `command` with RH/T compensation parameters and read the response
+    async fn command_with_compensation(
+        &mut self,
+        command: [u8; 2],
+        humidity: Option<f32>,
+        temp: Option<f32>,
+        read_buf: &mut [u8],
+    ) -> Result<(), Sgp41Error> {
         let rh_ticks = humidity
             .map(|h| Self::humidity_to_ticks(h))
             .unwrap_or(DEFAULT_RH_TICKS);
@@ -198,8 +391,8 @@ where
         let t_crc = crc8(&t_bytes);
 
         let buffer = [
-            CMD_MEASURE_RAW[0],
-            CMD_MEASURE_RAW[1],
+            command[0],
+            command[1],
             rh_bytes[0],
             rh_bytes[1],
             rh_crc,
@@ -208,27 +401,25 @@ where
             t_crc,
         ];
 
-        self.i2c
-            .write(self.address, &buffer)
-            .await
-            .map_err(|_| Sgp41Error::I2cError)?;
+        self.write(&buffer).await?;
 
         embassy_time::Timer::after_millis(MEASURE_DELAY_MS as u64).await;
 
-        let mut read_buf = [0u8; RESPONSE_SIZE];
-        self.i2c
-            .read(self.address, &mut read_buf)
-            .await
-            .map_err(|_| Sgp41Error::I2cError)?;
-
-        // Validate CRCs
-        Self::validate_crc(&read_buf[0..2], read_buf[2])?;
-        Self::validate_crc(&read_buf[3..5], read_buf[5])?;
+        self.read(read_buf).await
+    }
 
-        let voc_raw = u16::from_be_bytes([read_buf[0], read_buf[1]]);
-        let nox_raw = u16::from_be_bytes([read_buf[3], read_buf[4]]);
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Sgp41Error> {
+        with_timeout(self.timeout, self.i2c.write(self.address, bytes))
+            .await
+            .map_err(|_| Sgp41Error::Timeout)?
+            .map_err(|_| Sgp41Error::I2cError)
+    }
 
-        Ok((voc_raw, nox_raw))
+    async fn read(&mut self, buf: &mut [u8]) -> Result<(), Sgp41Error> {
+        with_timeout(self.timeout, self.i2c.read(self.address, buf))
+            .await
+            .map_err(|_| Sgp41Error::Timeout)?
+            .map_err(|_| Sgp41Error::I2cError)
     }
 
     /// Convert relative humidity percentage to SGP41 ticks
@@ -255,7 +446,15 @@ where
     }
 }
 
-fn crc8(data: &[u8]) -> u8 {
+impl<I2C: BusRecovery> Sgp41<I2C> {
+    /// Clear the I2C bus after repeated failures.
+    pub fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
+
+/// Sensirion CRC-8, shared by their I2C sensors.
+pub(crate) fn crc8(data: &[u8]) -> u8 {
     let mut crc = CRC_INIT;
     for &byte in data {
         crc ^= byte;
@@ -0,0 +1,247 @@
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::sensors::bus::BusRecovery;
+use crate::sensors::sgp41::crc8;
+
+// SHT40-AD1B; the BD1B variant answers on 0x45.
+const SHT4X_ADDRESS: u8 = 0x44;
+
+// Commands
+const CMD_MEASURE_HIGH_PRECISION: u8 = 0xFD;
+const CMD_MEASURE_MEDIUM_PRECISION: u8 = 0xF6;
+const CMD_MEASURE_LOW_PRECISION: u8 = 0xE0;
+const CMD_GET_SERIAL_NUMBER: u8 = 0x89;
+const CMD_SOFT_RESET: u8 = 0x94;
+
+// Timing, from the datasheet's maximum durations
+const MEASURE_HIGH_PRECISION_DELAY_MS: u64 = 9;
+const MEASURE_MEDIUM_PRECISION_DELAY_MS: u64 = 5;
+const MEASURE_LOW_PRECISION_DELAY_MS: u64 = 2;
+const HEATER_LONG_DELAY_MS: u64 = 1100;
+const HEATER_SHORT_DELAY_MS: u64 = 110;
+const SERIAL_NUMBER_DELAY_MS: u64 = 1;
+const SOFT_RESET_DELAY_MS: u64 = 1;
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+const RESPONSE_SIZE: usize = 6; // 2 bytes data + 1 CRC, twice
+
+/// Measurement repeatability; higher precision takes longer and more power.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sht4xPrecision {
+    High,
+    Medium,
+    Low,
+}
+
+impl Sht4xPrecision {
+    fn command(self) -> u8 {
+        match self {
+            Sht4xPrecision::High => CMD_MEASURE_HIGH_PRECISION,
+            Sht4xPrecision::Medium => CMD_MEASURE_MEDIUM_PRECISION,
+            Sht4xPrecision::Low => CMD_MEASURE_LOW_PRECISION,
+        }
+    }
+
+    fn delay_ms(self) -> u64 {
+        match self {
+            Sht4xPrecision::High => MEASURE_HIGH_PRECISION_DELAY_MS,
+            Sht4xPrecision::Medium => MEASURE_MEDIUM_PRECISION_DELAY_MS,
+            Sht4xPrecision::Low => MEASURE_LOW_PRECISION_DELAY_MS,
+        }
+    }
+}
+
+/// Heater power at a 3.3 V supply.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeaterPower {
+    Mw200,
+    Mw110,
+    Mw20,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeaterDuration {
+    /// 1 s.
+    Long,
+    /// 0.1 s.
+    Short,
+}
+
+/// Temperature and relative humidity.
+#[derive(Debug, Copy, Clone)]
+pub struct Sht4xMeasurement {
+    /// °C.
+    pub temperature: f32,
+    /// %RH, clamped to 0-100.
+    pub humidity: f32,
+}
+
+pub struct Sht4x<I2C> {
+    i2c: I2C,
+    address: u8,
+    precision: Sht4xPrecision,
+    timeout: Duration,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Sht4xError {
+    I2cError,
+    CrcError,
+    Timeout,
+}
+
+impl<I2C> Sht4x<I2C>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+{
+    /// Create a new SHT4x driver instance, measuring with high precision
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            address: SHT4X_ADDRESS,
+            precision: Sht4xPrecision::High,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Use a different I2C address, e.g. 0x45 for the SHT40-BD1B
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the precision used by `measure`
+    pub fn with_precision(mut self, precision: Sht4xPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set how long a single I2C transfer may take before failing with
+    /// `Sht4xError::Timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Measure temperature and humidity with the configured precision
+    pub async fn measure(&mut self) -> Result<Sht4xMeasurement, Sht4xError> {
+        let precision = self.precision;
+        self.command_and_read(precision.command(), precision.delay_ms())
+            .await
+    }
+
+    /// Run the heater, then measure with high precision
+    ///
+    /// Heating removes condensation and creep after long exposure to high
+    /// humidity. The returned reading is taken while the sensor is still
+    /// hot, so it doesn't reflect ambient conditions. The datasheet limits
+    /// the heater to a 10% duty cycle.
+    pub async fn heat(
+        &mut self,
+        power: HeaterPower,
+        duration: HeaterDuration,
+    ) -> Result<Sht4xMeasurement, Sht4xError> {
+        let command = match (power, duration) {
+            (HeaterPower::Mw200, HeaterDuration::Long) => 0x39,
+            (HeaterPower::Mw200, HeaterDuration::Short) => 0x32,
+            (HeaterPower::Mw110, HeaterDuration::Long) => 0x2F,
+            (HeaterPower::Mw110, HeaterDuration::Short) => 0x24,
+            (HeaterPower::Mw20, HeaterDuration::Long) => 0x1E,
+            (HeaterPower::Mw20, HeaterDuration::Short) => 0x15,
+        };
+        let delay_ms = match duration {
+            HeaterDuration::Long => HEATER_LONG_DELAY_MS,
+            HeaterDuration::Short => HEATER_SHORT_DELAY_MS,
+        };
+        self.command_and_read(command, delay_ms).await
+    }
+
+    /// Get the sensor's 32-bit serial number
+    pub async fn get_serial_number(&mut self) -> Result<u32, Sht4xError> {
+        let mut read_buf = [0u8; RESPONSE_SIZE];
+        self.write(&[CMD_GET_SERIAL_NUMBER]).await?;
+        Timer::after_millis(SERIAL_NUMBER_DELAY_MS).await;
+        self.read(&mut read_buf).await?;
+
+        Self::validate_crc(&read_buf[0..2], read_buf[2])?;
+        Self::validate_crc(&read_buf[3..5], read_buf[5])?;
+
+        Ok(u32::from_be_bytes([
+            read_buf[0],
+            read_buf[1],
+            read_buf[3],
+            read_buf[4],
+        ]))
+    }
+
+    /// Reset the sensor to its power-on state
+    pub async fn soft_reset(&mut self) -> Result<(), Sht4xError> {
+        self.write(&[CMD_SOFT_RESET]).await?;
+        Timer::after_millis(SOFT_RESET_DELAY_MS).await;
+        Ok(())
+    }
+
+    async fn command_and_read(
+        &mut self,
+        command: u8,
+        delay_ms: u64,
+    ) -> Result<Sht4xMeasurement, Sht4xError> {
+        let mut read_buf = [0u8; RESPONSE_SIZE];
+        self.write(&[command]).await?;
+        Timer::after_millis(delay_ms).await;
+        self.read(&mut read_buf).await?;
+
+        Self::validate_crc(&read_buf[0..2], read_buf[2])?;
+        Self::validate_crc(&read_buf[3..5], read_buf[5])?;
+
+        let t_ticks = u16::from_be_bytes([read_buf[0], read_buf[1]]);
+        let rh_ticks = u16::from_be_bytes([read_buf[3], read_buf[4]]);
+
+        Ok(Sht4xMeasurement {
+            temperature: Self::ticks_to_temperature(t_ticks),
+            humidity: Self::ticks_to_humidity(rh_ticks),
+        })
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Sht4xError> {
+        with_timeout(self.timeout, self.i2c.write(self.address, bytes))
+            .await
+            .map_err(|_| Sht4xError::Timeout)?
+            .map_err(|_| Sht4xError::I2cError)
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<(), Sht4xError> {
+        with_timeout(self.timeout, self.i2c.read(self.address, buf))
+            .await
+            .map_err(|_| Sht4xError::Timeout)?
+            .map_err(|_| Sht4xError::I2cError)
+    }
+
+    /// Convert SHT4x ticks to temperature in Celsius
+    /// Formula: °C = -45 + 175 * ticks / 65535
+    fn ticks_to_temperature(ticks: u16) -> f32 {
+        -45.0 + 175.0 * ticks as f32 / 65535.0
+    }
+
+    /// Convert SHT4x ticks to relative humidity percentage
+    /// Formula: %RH = -6 + 125 * ticks / 65535, cropped to 0-100
+    fn ticks_to_humidity(ticks: u16) -> f32 {
+        (-6.0 + 125.0 * ticks as f32 / 65535.0).clamp(0.0, 100.0)
+    }
+
+    /// Validate CRC8 checksum for received data
+    fn validate_crc(data: &[u8], expected_crc: u8) -> Result<(), Sht4xError> {
+        if crc8(data) == expected_crc {
+            Ok(())
+        } else {
+            Err(Sht4xError::CrcError)
+        }
+    }
+}
+
+impl<I2C: BusRecovery> Sht4x<I2C> {
+    /// Clear the I2C bus after repeated failures.
+    pub fn recover(&mut self) {
+        self.i2c.recover();
+    }
+}
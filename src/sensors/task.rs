@@ -1,22 +1,35 @@
-use crate::sensors::{SensorManager, SharedSensorData};
-use embassy_time::Timer;
+use crate::sensors::{SensorCommands, SensorManager, SharedSensorData};
+use embassy_futures::select::{Either, select};
+use embassy_time::Ticker;
 
 #[embassy_executor::task]
 pub async fn sensor_task(
     mut manager: SensorManager<
-        esp_hal::i2c::master::I2c<'static, esp_hal::Async>,
-        esp_hal::uart::Uart<'static, esp_hal::Async>,
-        esp_hal::uart::Uart<'static, esp_hal::Async>,
+        crate::sensors::bus::SharedI2c,
+        crate::sensors::pms5003t::PmsLink<
+            crate::sensors::bus::RecoverableUart,
+            crate::sensors::bus::SharedI2c,
+        >,
+        crate::sensors::bus::RecoverableUart,
     >,
     sensor_data: SharedSensorData,
+    commands: SensorCommands,
 ) -> ! {
     // Initialize sensors (e.g. SGP41 self-test and conditioning)
     defmt::info!("Initializing sensors...");
-    let _ = manager.init().await;
+    manager.init(&sensor_data).await;
     defmt::info!("Sensors initialized");
 
+    // A ticker keeps the period steady while PMS frames are collected for most
+    // of each interval.
+    let mut ticker = Ticker::every(crate::config::CONFIG.sensor.polling_interval);
     loop {
         manager.read_and_update(&sensor_data).await;
-        Timer::after(crate::config::CONFIG.sensor.polling_interval).await;
+
+        // Handle commands until the next poll is due.
+        while let Either::Second((id, command)) = select(ticker.next(), commands.receive()).await {
+            let result = manager.execute(command, &sensor_data).await;
+            commands.respond(id, result);
+        }
     }
 }
@@ -1,12 +1,19 @@
+use core::fmt::Write;
 use core::sync::atomic::AtomicU32;
 use embassy_net::Stack;
 use embassy_time::Duration;
 use esp_alloc as _;
-use picoserve::response::IntoResponse;
+use picoserve::extract::{FromRequestParts, Query};
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, StatusCode};
 use picoserve::{AppBuilder, AppRouter, Router, routing};
 
+use crate::config::CONFIG;
 use crate::metrics::metrics_handler;
-use crate::sensors::SharedSensorData;
+use crate::sensors::command::{SensorCommandError, TuningUpdate};
+use crate::sensors::gas_index::AlgorithmType;
+use crate::sensors::sgp41::{self, Sgp41Error, Sgp41SelfTest};
+use crate::sensors::{SensorCommand, SensorCommands, SharedSensorData};
 
 const ROOT_RESPONSE: &str = "OK";
 
@@ -14,6 +21,147 @@ pub async fn root_handler() -> impl IntoResponse {
     ROOT_RESPONSE
 }
 
+/// Extractor that rejects requests without `Authorization: Bearer <API_TOKEN>`.
+pub struct ApiAuth;
+
+impl<'r, State> FromRequestParts<'r, State> for ApiAuth {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(
+        _state: &'r State,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(token) = CONFIG.web.api_token else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "API_TOKEN was not set at build time\n",
+            ));
+        };
+
+        let provided = request_parts
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.as_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match provided {
+            Some(provided) if constant_time_eq(provided.as_bytes(), token.as_bytes()) => {
+                Ok(ApiAuth)
+            }
+            _ => Err((StatusCode::UNAUTHORIZED, "Unauthorized\n")),
+        }
+    }
+}
+
+/// Compare without exiting early, so response timing doesn't leak the token.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn calibrate_s8_handler(commands: SensorCommands) -> impl IntoResponse {
+    match commands.execute(SensorCommand::CalibrateS8).await {
+        Ok(()) => (StatusCode::OK, "Calibration acknowledged\n"),
+        Err(SensorCommandError::NotFitted) => (StatusCode::NOT_FOUND, "No S8 fitted\n"),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Calibration failed; see airgradient_sensor_error\n",
+        ),
+    }
+}
+
+/// Query parameters of `/scd4x/calibrate`.
+#[derive(serde::Deserialize)]
+pub struct CalibrationTarget {
+    /// Reference CO2 concentration in ppm.
+    pub ppm: u16,
+}
+
+pub async fn calibrate_scd4x_handler(
+    commands: SensorCommands,
+    target: CalibrationTarget,
+) -> impl IntoResponse {
+    match commands
+        .execute(SensorCommand::CalibrateScd4x {
+            target_ppm: target.ppm,
+        })
+        .await
+    {
+        Ok(()) => (StatusCode::OK, "Recalibration applied\n"),
+        Err(SensorCommandError::NotFitted) => (StatusCode::NOT_FOUND, "No SCD4x fitted\n"),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Recalibration failed; see airgradient_scd4x_calibration_success\n",
+        ),
+    }
+}
+
+pub async fn clean_sen5x_fan_handler(commands: SensorCommands) -> impl IntoResponse {
+    match commands.execute(SensorCommand::CleanSen5xFan).await {
+        Ok(()) => (StatusCode::OK, "Fan cleaning started\n"),
+        Err(SensorCommandError::NotFitted) => (StatusCode::NOT_FOUND, "No SEN5x/SEN66 fitted\n"),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Fan cleaning failed; see airgradient_sensor_error\n",
+        ),
+    }
+}
+
+pub async fn self_test_sgp41_handler(commands: SensorCommands) -> impl IntoResponse {
+    let result = match commands.execute(SensorCommand::SelfTestSgp41).await {
+        Ok(()) => Sgp41SelfTest::from_bits(sgp41::SELF_TEST_OK),
+        Err(SensorCommandError::Sgp41(Sgp41Error::SelfTestFailed(code))) => {
+            Sgp41SelfTest::from_bits(code)
+        }
+        Err(SensorCommandError::NotFitted) => {
+            let mut body = heapless::String::new();
+            let _ = body.push_str("No SGP41 in use\n");
+            return (StatusCode::NOT_FOUND, body);
+        }
+        Err(SensorCommandError::Busy) => {
+            let mut body = heapless::String::new();
+            let _ = body.push_str("SGP41 heater is off or its init is pending; try later\n");
+            return (StatusCode::SERVICE_UNAVAILABLE, body);
+        }
+        Err(_) => {
+            let mut body = heapless::String::new();
+            let _ = body.push_str("Self-test failed to run; see airgradient_sensor_error\n");
+            return (StatusCode::INTERNAL_SERVER_ERROR, body);
+        }
+    };
+
+    let pixel = |ok| if ok { "ok" } else { "failed" };
+    let mut body = heapless::String::<64>::new();
+    let _ = write!(
+        body,
+        "result: {:#06x}\nvoc_pixel: {}\nnox_pixel: {}\n",
+        result.bits(),
+        pixel(result.voc_pixel_ok()),
+        pixel(result.nox_pixel_ok())
+    );
+    (StatusCode::OK, body)
+}
+
+pub async fn tune_gas_index_handler(
+    commands: SensorCommands,
+    algorithm: AlgorithmType,
+    update: TuningUpdate,
+) -> impl IntoResponse {
+    match commands
+        .execute(SensorCommand::TuneGasIndex { algorithm, update })
+        .await
+    {
+        Ok(()) => (StatusCode::OK, "Tuning applied; learning restarted\n"),
+        Err(SensorCommandError::NotFitted) => (StatusCode::NOT_FOUND, "No SGP41 in use\n"),
+        Err(SensorCommandError::InvalidParameters) => (
+            StatusCode::BAD_REQUEST,
+            "Parameters out of range; see README\n",
+        ),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Tuning failed\n"),
+    }
+}
+
 pub const WEB_TASK_POOL_SIZE: usize = 2;
 
 #[embassy_executor::task(pool_size = WEB_TASK_POOL_SIZE)]
@@ -40,9 +188,14 @@ pub struct WebApp {
 }
 
 impl WebApp {
-    pub fn new(sensor_data: SharedSensorData, last_scrape_secs: &'static AtomicU32) -> Self {
+    pub fn new(
+        sensor_data: SharedSensorData,
+        sensor_commands: SensorCommands,
+        last_scrape_secs: &'static AtomicU32,
+    ) -> Self {
         let app = Application {
             sensor_data,
+            sensor_commands,
             device_info: crate::device::DeviceInfo::get(),
             reset_reason: crate::device::resolve_reset_reason(esp_hal::system::reset_reason()),
             last_scrape_secs,
@@ -67,6 +220,7 @@ impl WebApp {
 #[derive(Clone)]
 pub struct Application {
     pub sensor_data: SharedSensorData,
+    pub sensor_commands: SensorCommands,
     pub device_info: crate::device::DeviceInfo,
     pub reset_reason: &'static str,
     pub last_scrape_secs: &'static AtomicU32,
@@ -78,6 +232,7 @@ impl AppBuilder for Application {
     fn build_app(self) -> Router<Self::PathRouter> {
         let Self {
             sensor_data,
+            sensor_commands,
             device_info,
             reset_reason,
             last_scrape_secs,
@@ -95,5 +250,35 @@ impl AppBuilder for Application {
                     )
                 }),
             )
+            .route(
+                "/s8/calibrate",
+                routing::post(move |_: ApiAuth| calibrate_s8_handler(sensor_commands)),
+            )
+            .route(
+                "/scd4x/calibrate",
+                routing::post(move |_: ApiAuth, Query(target): Query<CalibrationTarget>| {
+                    calibrate_scd4x_handler(sensor_commands, target)
+                }),
+            )
+            .route(
+                "/sen5x/fan-cleaning",
+                routing::post(move |_: ApiAuth| clean_sen5x_fan_handler(sensor_commands)),
+            )
+            .route(
+                "/sgp41/self-test",
+                routing::post(move |_: ApiAuth| self_test_sgp41_handler(sensor_commands)),
+            )
+            .route(
+                "/sgp41/tuning/voc",
+                routing::post(move |_: ApiAuth, Query(update): Query<TuningUpdate>| {
+                    tune_gas_index_handler(sensor_commands, AlgorithmType::Voc, update)
+                }),
+            )
+            .route(
+                "/sgp41/tuning/nox",
+                routing::post(move |_: ApiAuth, Query(update): Query<TuningUpdate>| {
+                    tune_gas_index_handler(sensor_commands, AlgorithmType::Nox, update)
+                }),
+            )
     }
 }